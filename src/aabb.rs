@@ -1,4 +1,4 @@
-use crate::{hittable::HitRecord, objects::Hittable, ray::Ray, vec3::Vec3};
+use crate::{hittable::HitRecord, objects::Hittable, random::Random, ray::Ray, vec3::Vec3};
 
 #[derive(Default, Copy, Clone, Debug, PartialOrd, PartialEq)]
 pub struct Aabb {
@@ -25,7 +25,7 @@ impl Aabb {
     }
 }
 impl Hittable for Aabb {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let mut t_min = t_min;
         let mut t_max = t_max;
 
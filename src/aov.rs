@@ -0,0 +1,143 @@
+use std::{error::Error, fmt::Display, str::FromStr, sync::Arc};
+
+use rand::RngCore;
+
+use crate::{
+    camera::Camera, hittable::HitRecord, random::SampleRng, renderer::Framebuffer, vec3::Vec3,
+    Material,
+};
+
+/// Auxiliary buffer (arbitrary output variable) of what the camera rays hit first,
+/// rendered next to the image for compositing and denoising. Pixels where nothing was
+/// hit are zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// Color of the surface, from its material's texture.
+    Albedo,
+    /// Outward normal of the surface, in world space.
+    Normal,
+    /// Distance of the surface along the camera's view direction.
+    Depth,
+    /// World-space position of the surface.
+    Position,
+    /// Number of the material hit, from 1 in the order the materials first appear in
+    /// the image, row by row. Not averaged: each pixel takes the material hit by most
+    /// of its samples.
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::MaterialId,
+    ];
+
+    /// Value of a sample whose camera ray first hit `rec`. The material ID is numbered
+    /// by the renderer once the whole image is known, so it is zero here.
+    pub fn value(self, rec: &HitRecord, camera: &Camera) -> Vec3 {
+        match self {
+            Aov::Albedo => rec.material.as_ref().albedo(rec),
+            Aov::Normal if rec.front_face => rec.normal,
+            Aov::Normal => -rec.normal,
+            Aov::Depth => {
+                let depth = Vec3::dot(rec.p - camera.origin, -camera.w);
+                Vec3::new(depth, depth, depth)
+            }
+            Aov::Position => rec.p,
+            Aov::MaterialId => Vec3::default(),
+        }
+    }
+
+    /// Identifies the material hit at `rec` for `Aov::MaterialId`; 0 if there is none.
+    pub fn material_key(rec: &HitRecord) -> usize {
+        rec.material
+            .as_ref()
+            .map_or(0, |material| Arc::as_ptr(material) as *const () as usize)
+    }
+
+    /// Maps a buffer of this AOV to colors between 0 and 1 for 8-bit image formats:
+    /// normals from [-1, 1] per axis, depth white in front of the camera fading to
+    /// black at the farthest pixel, positions scaled to the range seen in the image and
+    /// every material ID to a color of its own.
+    pub fn to_display(self, framebuffer: &Framebuffer) -> Framebuffer {
+        let mut display = framebuffer.clone();
+        match self {
+            Aov::Albedo => {}
+            Aov::Normal => {
+                for pixel in &mut display.pixels {
+                    *pixel = (*pixel + Vec3::new(1., 1., 1.)) * 0.5;
+                }
+            }
+            Aov::Depth => {
+                let far = framebuffer
+                    .pixels
+                    .iter()
+                    .map(|pixel| pixel.x_r)
+                    .fold(0., f64::max);
+                for pixel in &mut display.pixels {
+                    if pixel.x_r > 0. {
+                        let brightness = 1. - pixel.x_r / far;
+                        *pixel = Vec3::new(brightness, brightness, brightness);
+                    }
+                }
+            }
+            Aov::Position => {
+                for axis in 0..3 {
+                    let (min, max) = framebuffer
+                        .pixels
+                        .iter()
+                        .fold((f64::MAX, f64::MIN), |(min, max), pixel| {
+                            (min.min(pixel[axis]), max.max(pixel[axis]))
+                        });
+                    let range = (max - min).max(f64::MIN_POSITIVE);
+                    for pixel in &mut display.pixels {
+                        pixel[axis] = (pixel[axis] - min) / range;
+                    }
+                }
+            }
+            Aov::MaterialId => {
+                for pixel in &mut display.pixels {
+                    let id = pixel.x_r as u64;
+                    if id > 0 {
+                        let mut rng = SampleRng::new(id);
+                        let mut channel = || 0.2 + 0.8 * (rng.next_u32() as f64 / u32::MAX as f64);
+                        *pixel = Vec3::new(channel(), channel(), channel());
+                    }
+                }
+            }
+        }
+        display
+    }
+}
+
+/// Parses one AOV name: `albedo`, `normal`, `depth`, `position` or `id`.
+impl FromStr for Aov {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "albedo" => Ok(Aov::Albedo),
+            "normal" => Ok(Aov::Normal),
+            "depth" => Ok(Aov::Depth),
+            "position" => Ok(Aov::Position),
+            "id" => Ok(Aov::MaterialId),
+            _ => Err(format!("unknown AOV '{}'", name).into()),
+        }
+    }
+}
+
+impl Display for Aov {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::MaterialId => "id",
+        };
+        f.write_str(name)
+    }
+}
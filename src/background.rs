@@ -0,0 +1,382 @@
+use std::{
+    error::Error,
+    f64::consts::{self, PI},
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::Arc,
+};
+
+use image::{
+    codecs::hdr::{HdrDecoder, HdrMetadata},
+    Rgb32FImage,
+};
+
+use crate::{
+    aabb::Aabb, hittable::HitRecord, random::Random, ray::Ray, texture::image_texture, vec3::Vec3,
+    Hittable,
+};
+
+/// Radiance arriving along rays that leave the scene without hitting anything.
+#[derive(Clone)]
+pub enum Background {
+    Solid(Vec3),
+    /// Blends from `bottom` straight down to `top` straight up, like the sky of
+    /// "Ray Tracing in One Weekend".
+    Gradient {
+        bottom: Vec3,
+        top: Vec3,
+    },
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid(Vec3::default())
+    }
+}
+
+impl Background {
+    /// The blue sky gradient of "Ray Tracing in One Weekend".
+    pub fn sky() -> Self {
+        Background::Gradient {
+            bottom: Vec3::new(1., 1., 1.),
+            top: Vec3::new(0.5, 0.7, 1.),
+        }
+    }
+
+    pub fn value(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (Vec3::unit_vector(direction).y_g + 1.);
+                *bottom * (1. - t) + *top * t
+            }
+            Background::Environment(map) => map.value(direction),
+        }
+    }
+}
+
+/// Equirectangular (latitude-longitude) image of the radiance around the scene,
+/// mapped like the textures of spheres: +y is the top row and the middle column
+/// looks towards +x.
+///
+/// Directions are sampled in proportion to the luminance of the texels seen in them,
+/// so a small bright sun is found as easily as a lamp in the scene.
+pub struct EnvironmentMap {
+    pub(crate) image: Rgb32FImage,
+    /// Rotation of the map around the y axis, in degrees.
+    pub rotation: f64,
+    /// Factor every texel is multiplied by.
+    pub intensity: f64,
+    /// Chance of sampling each row, by the luminance summed over it.
+    rows: Distribution,
+    /// Chance of sampling each texel of a row, once the row is chosen.
+    columns: Vec<Distribution>,
+}
+
+impl EnvironmentMap {
+    /// Loads a map from a Radiance HDR (`.hdr`) file, or any other image format
+    /// `image` can decode, such as OpenEXR (`.exr`).
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let image = Self::load_image(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self::from_image(image))
+    }
+    pub fn from_image(image: Rgb32FImage) -> Self {
+        let (width, height) = image.dimensions();
+        // Rows nearer the poles cover less of the sphere.
+        let columns: Vec<Distribution> = (0..height)
+            .map(|row| {
+                let latitude = (0.5 - (row as f64 + 0.5) / height as f64) * PI;
+                let weights: Vec<f64> = (0..width)
+                    .map(|column| {
+                        let pixel = image.get_pixel(column, row);
+                        let color = Vec3::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+                        Vec3::luminance(color).max(0.) * latitude.cos()
+                    })
+                    .collect();
+                Distribution::new(&weights)
+            })
+            .collect();
+        let row_weights: Vec<f64> = columns.iter().map(|columns| columns.total).collect();
+        Self {
+            image,
+            rotation: 0.,
+            intensity: 1.,
+            rows: Distribution::new(&row_weights),
+            columns,
+        }
+    }
+    /// Decodes `.hdr` files directly, as `image::open` would clamp them to 8 bits.
+    /// Other images are decoded from sRGB unless they are floating-point.
+    fn load_image(path: &Path) -> Result<Rgb32FImage, Box<dyn Error>> {
+        if !path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"))
+        {
+            return Ok(image_texture::linear_rgb(image::open(path)?));
+        }
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let HdrMetadata { width, height, .. } = decoder.metadata();
+        let pixels = decoder.read_image_hdr()?;
+        let data = pixels.iter().flat_map(|pixel| pixel.0).collect();
+        Ok(Rgb32FImage::from_raw(width, height, data).ok_or("truncated image data")?)
+    }
+    pub fn rotation(mut self, rotation: f64) -> Self {
+        self.rotation = rotation;
+        self
+    }
+    pub fn intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn value(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = self.texel(direction);
+        let pixel = self.image.get_pixel(x, y);
+        Vec3::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64) * self.intensity
+    }
+
+    /// Direction drawn in proportion to the luminance seen in it.
+    pub fn sample(&self, rng: &mut Random<f64>) -> Vec3 {
+        if self.rows.total == 0. {
+            return Vec3::random_unit_vector(rng);
+        }
+        let (row, row_offset) = self.rows.sample(rng.random(None, None));
+        let (column, column_offset) = self.columns[row].sample(rng.random(None, None));
+        let (width, height) = self.image.dimensions();
+        let u = (column as f64 + column_offset) / width as f64;
+        let v = 1. - (row as f64 + row_offset) / height as f64;
+        let (sin_longitude, cos_longitude) = ((u - 0.5) * 2. * PI).sin_cos();
+        let (sin_latitude, cos_latitude) = ((v - 0.5) * PI).sin_cos();
+        let (x, z) = (cos_longitude * cos_latitude, -sin_longitude * cos_latitude);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        Vec3::new(x * cos - z * sin, sin_latitude, z * cos + x * sin)
+    }
+
+    /// Density, per unit solid angle, with which `sample` draws `direction`.
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        // Black maps are sampled uniformly.
+        if self.rows.total == 0. {
+            return 0.25 * consts::FRAC_1_PI;
+        }
+        let cos_latitude = (1. - Vec3::unit_vector(direction).y_g.powi(2))
+            .max(0.)
+            .sqrt();
+        if cos_latitude == 0. {
+            return 0.;
+        }
+        let (column, row) = self.texel(direction);
+        let (width, height) = self.image.dimensions();
+        let probability = self.rows.probability(row as usize)
+            * self.columns[row as usize].probability(column as usize);
+        // The texel covers 1 / (width * height) of the unit square of (u, v), which
+        // maps onto 2π² cos(latitude) of solid angle per unit area.
+        probability * (width * height) as f64 / (2. * PI * PI * cos_latitude)
+    }
+
+    /// Column and row of the texel seen in `direction`.
+    fn texel(&self, direction: Vec3) -> (u32, u32) {
+        let d = Vec3::unit_vector(direction);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (x, z) = (d.x_r * cos + d.z_b * sin, d.z_b * cos - d.x_r * sin);
+        let u = 0.5 + f64::atan2(-z, x) * 0.5 * consts::FRAC_1_PI;
+        let v = 0.5 + f64::asin(d.y_g.clamp(-1., 1.)) * consts::FRAC_1_PI;
+        let (width, height) = self.image.dimensions();
+        let column = ((u * width as f64) as u32).min(width - 1);
+        let row = (((1. - v) * height as f64) as u32).min(height - 1);
+        (column, row)
+    }
+}
+
+/// Lets the map be sampled like the lights in the scene. It surrounds the scene at
+/// infinity, so rays never hit it; they see it in the background when they miss.
+impl Hittable for EnvironmentMap {
+    fn hit(
+        &self,
+        _ray: &Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _rng: &mut Random<f64>,
+    ) -> Option<HitRecord> {
+        None
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64, _output_box: &mut Aabb) -> bool {
+        false
+    }
+    fn pdf_value(&self, _origin: Vec3, direction: Vec3, _rng: &mut Random<f64>) -> f64 {
+        self.pdf(direction)
+    }
+    fn random(&self, _origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        self.sample(rng)
+    }
+}
+
+/// Piecewise-constant distribution over `0..n`, sampled by inverting its CDF.
+struct Distribution {
+    /// `cdf[i]` is the chance of drawing less than `i`, up to `cdf[n] = 1`.
+    cdf: Vec<f64>,
+    /// Sum of the weights.
+    total: f64,
+}
+
+impl Distribution {
+    fn new(weights: &[f64]) -> Self {
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        let mut sum = 0.;
+        cdf.push(sum);
+        for weight in weights {
+            sum += weight;
+            cdf.push(sum);
+        }
+        if sum > 0. {
+            cdf.iter_mut().for_each(|value| *value /= sum);
+        }
+        Self { cdf, total: sum }
+    }
+
+    /// The index `r` (in `[0, 1)`) falls on and where in its interval, from 0 to 1.
+    fn sample(&self, r: f64) -> (usize, f64) {
+        let index = (self.cdf.partition_point(|&value| value <= r) - 1).min(self.cdf.len() - 2);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0. {
+            ((r - self.cdf[index]) / width).clamp(0., 1.)
+        } else {
+            0.5
+        };
+        (index, offset)
+    }
+
+    fn probability(&self, index: usize) -> f64 {
+        self.cdf[index + 1] - self.cdf[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::random::SampleRng;
+
+    const SAMPLES: usize = 400_000;
+
+    fn random(seed: u64) -> Random<f64> {
+        Random::new(SampleRng::new(seed), Uniform::new(0., 1.))
+    }
+
+    /// 16×8 map with a bright spot, a black band and colors varying in between.
+    fn map() -> EnvironmentMap {
+        let image = Rgb32FImage::from_fn(16, 8, |x, y| {
+            if (x, y) == (11, 2) {
+                Rgb([60., 50., 40.])
+            } else if y == 5 {
+                Rgb([0., 0., 0.])
+            } else {
+                let value = ((x * 7 + y * 3) % 5) as f32 * 0.5;
+                Rgb([value, 0.5 * value + 0.1, 0.2])
+            }
+        });
+        EnvironmentMap::from_image(image).rotation(30.)
+    }
+
+    #[test]
+    fn distribution_inverts_its_cdf() {
+        let weights = [0., 1., 3., 0., 4.];
+        let distribution = Distribution::new(&weights);
+        assert_eq!(distribution.total, 8.);
+        let mut counts = [0; 5];
+        let steps = 8000;
+        for i in 0..steps {
+            let (index, offset) = distribution.sample((i as f64 + 0.5) / steps as f64);
+            assert!((0. ..=1.).contains(&offset));
+            counts[index] += 1;
+        }
+        for (index, weight) in weights.iter().enumerate() {
+            assert!((distribution.probability(index) - weight / 8.).abs() < 1e-12);
+            assert_eq!(counts[index], (weight / 8. * steps as f64) as usize);
+        }
+    }
+
+    #[test]
+    fn sampling_matches_the_pdf() {
+        let map = map();
+        let axis = Vec3::unit_vector(Vec3::new(0.3, -0.5, 0.8));
+        let test = |direction: Vec3| (1. + Vec3::dot(direction, axis)).powi(2);
+
+        // Texels are weighted by the area of their row's center, so luminance over
+        // density, corrected for that, is the same for every sample: the map's power.
+        let mut rng = random(1);
+        let mut sampled = 0.;
+        let mut ratios = vec![];
+        for _ in 0..SAMPLES {
+            let direction = map.sample(&mut rng);
+            assert!((Vec3::length(direction) - 1.).abs() < 1e-9);
+            let pdf = map.pdf(direction);
+            assert!(pdf > 0., "sampled a direction of zero density");
+            let (_, row) = map.texel(direction);
+            let row_latitude = (0.5 - (row as f64 + 0.5) / 8.) * PI;
+            let cos_latitude = (1. - direction.y_g.powi(2)).sqrt();
+            ratios.push(
+                Vec3::luminance(map.value(direction)) * row_latitude.cos() / cos_latitude / pdf,
+            );
+            sampled += test(direction);
+        }
+        sampled /= SAMPLES as f64;
+        ratios.sort_by(f64::total_cmp);
+        let median = ratios[SAMPLES / 2];
+        let outliers = ratios
+            .iter()
+            .filter(|&&ratio| (ratio - median).abs() > 1e-6 * median)
+            .count();
+        // Only samples on the edge of a texel may round into its neighbour.
+        assert!(outliers < SAMPLES / 1000, "{} outliers", outliers);
+
+        let mut rng = random(2);
+        let mut integral = 0.;
+        let mut total = 0.;
+        let mut power = 0.;
+        for _ in 0..SAMPLES {
+            let z = 1. - 2. * rng.random(None, None);
+            let phi = 2. * PI * rng.random(None, None);
+            let r = (1. - z * z).max(0.).sqrt();
+            let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            let pdf = map.pdf(direction) * 4. * PI;
+            integral += test(direction) * pdf;
+            total += pdf;
+            power += Vec3::luminance(map.value(direction)) * 4. * PI;
+        }
+        integral /= SAMPLES as f64;
+        total /= SAMPLES as f64;
+        power /= SAMPLES as f64;
+        assert!((total - 1.).abs() < 0.02, "pdf integrates to {}", total);
+        assert!(
+            (integral - sampled).abs() < 0.02 * sampled,
+            "samples average {}, the pdf gives {}",
+            sampled,
+            integral
+        );
+        assert!(
+            (power - median).abs() < 0.03 * power,
+            "power {}, luminance over pdf {}",
+            power,
+            median
+        );
+    }
+
+    #[test]
+    fn black_maps_are_sampled_uniformly() {
+        let map = EnvironmentMap::from_image(Rgb32FImage::new(8, 4));
+        let mut rng = random(3);
+        let mut mean = Vec3::default();
+        for _ in 0..10_000 {
+            let direction = map.sample(&mut rng);
+            assert!((Vec3::length(direction) - 1.).abs() < 1e-9);
+            assert_eq!(map.pdf(direction), 1. / (4. * PI));
+            mean += direction / 10_000.;
+        }
+        assert!(Vec3::length(mean) < 0.05);
+        assert_eq!(map.pdf(Vec3::new(0., 1., 0.)), 1. / (4. * PI));
+    }
+}
@@ -1,9 +1,56 @@
 use rayon::prelude::ParallelSliceMut;
-use std::cmp::Ordering;
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
 
 use std::sync::Arc;
 
-use crate::{aabb::Aabb, hittable::HitRecord, objects::Hittable, ray::Ray};
+use crate::{aabb::Aabb, hittable::HitRecord, objects::Hittable, random::Random, ray::Ray};
+
+thread_local! {
+    static BOX_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Number of `BoxTestCounting`s alive; box tests are only counted while there is one.
+static COUNTING: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of BVH bounding boxes, of `BvhNode`s and of mesh BVHs, that rays have been
+/// tested against on this thread while box tests were counted.
+pub fn box_tests() -> u64 {
+    BOX_TESTS.with(Cell::get)
+}
+
+#[inline]
+pub(crate) fn count_box_test() {
+    if COUNTING.load(AtomicOrdering::Relaxed) > 0 {
+        BOX_TESTS.with(|tests| tests.set(tests.get() + 1));
+    }
+}
+
+/// Makes `box_tests` count for as long as it is alive, so that traversals don't pay
+/// for the counter when nobody reads it.
+pub struct BoxTestCounting(());
+
+impl BoxTestCounting {
+    pub fn new() -> Self {
+        COUNTING.fetch_add(1, AtomicOrdering::Relaxed);
+        Self(())
+    }
+}
+
+impl Default for BoxTestCounting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for BoxTestCounting {
+    fn drop(&mut self) {
+        COUNTING.fetch_sub(1, AtomicOrdering::Relaxed);
+    }
+}
 #[derive(Default)]
 pub struct BvhNode {
     pub left: Option<Arc<dyn Hittable>>,
@@ -12,13 +59,14 @@ pub struct BvhNode {
 }
 
 impl Hittable for BvhNode {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        self.bbox.hit(ray, t_min, t_max)?;
-        let hit_left = self.left.hit(ray, t_min, t_max);
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
+        count_box_test();
+        self.bbox.hit(ray, t_min, t_max, rng)?;
+        let hit_left = self.left.hit(ray, t_min, t_max, rng);
         let hit_right = if let Some(rec) = hit_left.as_ref() {
-            self.right.hit(ray, t_min, rec.t)
+            self.right.hit(ray, t_min, rec.t, rng)
         } else {
-            self.right.hit(ray, t_min, t_max)
+            self.right.hit(ray, t_min, t_max, rng)
         };
 
         if let Some(rec) = hit_left {
@@ -36,6 +84,18 @@ impl Hittable for BvhNode {
         }
     }
 
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if let Some(left) = &self.left {
+            left.clone().collect_lights(lights);
+        }
+        // A node of a single object holds it on both sides.
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) if Arc::ptr_eq(left, right) => {}
+            (_, Some(right)) => right.clone().collect_lights(lights),
+            _ => {}
+        }
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
         *output_box = self.bbox;
         true
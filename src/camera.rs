@@ -11,8 +11,6 @@ pub struct Camera {
     pub v: Vec3,
     pub w: Vec3,
 
-    pub aspect_ratio: f64,
-
     pub origin: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
@@ -54,7 +52,6 @@ impl Camera {
             u,
             v,
             w,
-            aspect_ratio,
             origin: lookfrom,
             horizontal,
             vertical,
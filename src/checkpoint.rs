@@ -0,0 +1,284 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    film::{Film, PixelStats},
+    filter::{Filter, FilterKind},
+    integrator::IntegratorKind,
+    renderer::{AdaptiveSampling, BounceLimits, MisHeuristic, RenderSettings},
+    vec3::Vec3,
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT05";
+
+/// Everything needed to continue an interrupted progressive render: the raw
+/// accumulation buffer, what it is a render of and how far it got.
+///
+/// Every sample draws from its own random stream, derived from the seed and the
+/// sample's index within its pixel, so the seed and the per-pixel sample counts in
+/// the film are the whole sampler state.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub scene: String,
+    pub output: String,
+    /// Settings of the render; `threads` is not stored and always `None` after loading.
+    pub settings: RenderSettings,
+    pub samples_per_pass: u32,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to a temporary file next to `path` and renames it into
+    /// place, so an interrupted write never destroys the previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let temp_path = path.with_extension("checkpoint.tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(MAGIC)?;
+        Self::write_str(&mut writer, &self.scene)?;
+        Self::write_str(&mut writer, &self.output)?;
+        let settings = &self.settings;
+        writer.write_all(&settings.width.to_le_bytes())?;
+        writer.write_all(&settings.height.to_le_bytes())?;
+        writer.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+        writer.write_all(&[settings.max_depth, settings.roulette_depth])?;
+        let bounces = settings.bounces;
+        for limit in [
+            bounces.diffuse,
+            bounces.glossy,
+            bounces.transmission,
+            bounces.volume,
+        ] {
+            writer.write_all(&[limit.is_some() as u8, limit.unwrap_or(0)])?;
+        }
+        writer.write_all(&settings.seed.to_le_bytes())?;
+        Self::write_str(&mut writer, &settings.filter.kind.to_string())?;
+        writer.write_all(&settings.filter.radius.to_le_bytes())?;
+        Self::write_str(&mut writer, &settings.mis.to_string())?;
+        Self::write_str(&mut writer, &settings.integrator.to_string())?;
+        match settings.adaptive {
+            Some(adaptive) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&adaptive.min_samples_per_pixel.to_le_bytes())?;
+                writer.write_all(&adaptive.max_samples_per_pixel.to_le_bytes())?;
+                writer.write_all(&adaptive.noise_threshold.to_le_bytes())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.write_all(&self.samples_per_pass.to_le_bytes())?;
+        let film = &self.film;
+        writer.write_all(&film.width.to_le_bytes())?;
+        writer.write_all(&film.height.to_le_bytes())?;
+        for pixel in &film.pixels {
+            for component in [pixel.x_r, pixel.y_g, pixel.z_b] {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+        for weight in &film.weights {
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        for stats in &film.stats {
+            writer.write_all(&stats.count.to_le_bytes())?;
+            writer.write_all(&stats.luminance_sum.to_le_bytes())?;
+            writer.write_all(&stats.luminance_square_sum.to_le_bytes())?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::read(&mut reader).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    fn read(reader: &mut impl Read) -> Result<Self, Box<dyn Error>> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("not a render checkpoint".into());
+        }
+        let scene = Self::read_str(reader)?;
+        let output = Self::read_str(reader)?;
+        let width = u32::from_le_bytes(Self::read_bytes(reader)?);
+        let height = u32::from_le_bytes(Self::read_bytes(reader)?);
+        let samples_per_pixel = u32::from_le_bytes(Self::read_bytes(reader)?);
+        let [max_depth, roulette_depth] = Self::read_bytes(reader)?;
+        let mut limit = || -> Result<Option<u8>, Box<dyn Error>> {
+            let [present, limit] = Self::read_bytes(reader)?;
+            Ok((present == 1).then_some(limit))
+        };
+        let bounces = BounceLimits {
+            diffuse: limit()?,
+            glossy: limit()?,
+            transmission: limit()?,
+            volume: limit()?,
+        };
+        let seed = u64::from_le_bytes(Self::read_bytes(reader)?);
+        let filter_kind: FilterKind = Self::read_str(reader)?.parse()?;
+        let filter_radius = f64::from_le_bytes(Self::read_bytes(reader)?);
+        let mis: MisHeuristic = Self::read_str(reader)?.parse()?;
+        let integrator: IntegratorKind = Self::read_str(reader)?.parse()?;
+        let mut settings = RenderSettings::new()
+            .width(width)
+            .height(height)
+            .samples_per_pixel(samples_per_pixel)
+            .max_depth(max_depth)
+            .bounces(bounces)
+            .roulette_depth(roulette_depth)
+            .seed(seed)
+            .filter(Filter::new(filter_kind).radius(filter_radius))
+            .mis(mis)
+            .integrator(integrator);
+        if Self::read_bytes(reader)? == [1] {
+            let min_samples = u32::from_le_bytes(Self::read_bytes(reader)?);
+            let max_samples = u32::from_le_bytes(Self::read_bytes(reader)?);
+            let noise_threshold = f64::from_le_bytes(Self::read_bytes(reader)?);
+            settings = settings.adaptive(
+                AdaptiveSampling::new(noise_threshold)
+                    .min_samples_per_pixel(min_samples)
+                    .max_samples_per_pixel(max_samples),
+            );
+        }
+        let samples_per_pass = u32::from_le_bytes(Self::read_bytes(reader)?);
+        let film_width = u32::from_le_bytes(Self::read_bytes(reader)?);
+        let film_height = u32::from_le_bytes(Self::read_bytes(reader)?);
+        if (film_width, film_height) != (width, height) {
+            return Err("film size does not match the render settings".into());
+        }
+        let mut film = Film::new(width, height);
+        for pixel in &mut film.pixels {
+            let mut component = || -> Result<f64, Box<dyn Error>> {
+                Ok(f64::from_le_bytes(Self::read_bytes(reader)?))
+            };
+            *pixel = Vec3::new(component()?, component()?, component()?);
+        }
+        for weight in &mut film.weights {
+            *weight = f64::from_le_bytes(Self::read_bytes(reader)?);
+        }
+        for stats in &mut film.stats {
+            *stats = PixelStats {
+                count: u32::from_le_bytes(Self::read_bytes(reader)?),
+                luminance_sum: f64::from_le_bytes(Self::read_bytes(reader)?),
+                luminance_square_sum: f64::from_le_bytes(Self::read_bytes(reader)?),
+            };
+        }
+        Ok(Self {
+            scene,
+            output,
+            settings,
+            samples_per_pass,
+            film,
+        })
+    }
+
+    fn write_str(writer: &mut impl Write, text: &str) -> Result<(), Box<dyn Error>> {
+        writer.write_all(&(text.len() as u32).to_le_bytes())?;
+        writer.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_str(reader: &mut impl Read) -> Result<String, Box<dyn Error>> {
+        let len = u32::from_le_bytes(Self::read_bytes(reader)?) as usize;
+        let mut bytes = vec![0; len];
+        reader.read_exact(&mut bytes)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    fn read_bytes<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], Box<dyn Error>> {
+        let mut bytes = [0; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::{FilmTile, TileBounds};
+
+    fn checkpoint() -> Checkpoint {
+        let settings = RenderSettings::new()
+            .width(5)
+            .height(3)
+            .samples_per_pixel(64)
+            .max_depth(12)
+            .roulette_depth(4)
+            .bounces(BounceLimits::new().glossy(3).volume(0))
+            .seed(0xDEAD_BEEF_1234)
+            .filter(Filter::new(FilterKind::Mitchell).radius(1.75))
+            .mis(MisHeuristic::Balance)
+            .integrator(IntegratorKind::new("ao", Some(2.5)).unwrap())
+            .adaptive(
+                AdaptiveSampling::new(0.02)
+                    .min_samples_per_pixel(8)
+                    .max_samples_per_pixel(256),
+            );
+        let mut film = Film::new(5, 3);
+        let bounds = TileBounds {
+            x0: 0,
+            y0: 0,
+            x1: 5,
+            y1: 3,
+        };
+        let mut tile = FilmTile::new(bounds, settings.filter, 5, 3);
+        for (i, radiance) in [0.5, 3., 0.125, 7.25].into_iter().enumerate() {
+            let position = (1.2 + i as f64 * 0.7, 0.4 + i as f64 * 0.5);
+            let pixel = (position.0 as u32, position.1 as u32);
+            tile.add_sample(pixel, position, Vec3::new(radiance, 1., -radiance));
+        }
+        film.merge_tile(&tile);
+        Checkpoint {
+            scene: "scenes/cornell_box.scene".to_string(),
+            output: "render/ünïcode.exr".to_string(),
+            settings,
+            samples_per_pass: 4,
+            film,
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "raytracing_checkpoint_test_{}.checkpoint",
+            std::process::id()
+        ));
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.scene, saved.scene);
+        assert_eq!(loaded.output, saved.output);
+        assert_eq!(loaded.settings, saved.settings);
+        assert_eq!(loaded.samples_per_pass, saved.samples_per_pass);
+        assert_eq!(
+            (loaded.film.width, loaded.film.height),
+            (saved.film.width, saved.film.height)
+        );
+        assert_eq!(loaded.film.pixels, saved.film.pixels);
+        assert_eq!(loaded.film.weights, saved.film.weights);
+        assert_eq!(loaded.film.stats, saved.film.stats);
+        assert_eq!(loaded.film.sample_count(), 4);
+    }
+
+    #[test]
+    fn truncated_or_foreign_files_are_rejected() {
+        let path = std::env::temp_dir().join(format!(
+            "raytracing_checkpoint_test_{}_truncated.checkpoint",
+            std::process::id()
+        ));
+        checkpoint().save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert!(Checkpoint::read(&mut bytes.as_slice()).is_err());
+        bytes[0] = b'X';
+        let err = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "not a render checkpoint");
+    }
+}
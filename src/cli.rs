@@ -0,0 +1,345 @@
+use std::{error::Error, path::Path};
+
+use raytracing_in_one_week::{
+    aov::Aov,
+    denoiser::Denoiser,
+    filter::{Filter, FilterKind},
+    integrator::IntegratorKind,
+    output::{ImageFormat, ImageOutput},
+    renderer::{AdaptiveSampling, BounceLimits, MisHeuristic},
+    tone_map::{ToneMapOperator, ToneMapper},
+};
+
+pub const USAGE: &str = "\
+Usage: ./raytracer [OPTIONS] [SCENE]
+
+SCENE is either the number of a built-in scene (0-7), a path to a .scene file or
+a path to a glTF 2.0 file (.gltf/.glb).
+
+Progressive renders also save their progress to the output path with the
+extension .checkpoint, which is removed once the render finishes.
+
+Options:
+  -s, --scene <SCENE>        Scene to render (same as the positional argument) [default: 0]
+  -W, --width <PIXELS>       Image width [default: 1280]
+  -H, --height <PIXELS>      Image height [default: width * 9 / 16]
+  -n, --samples <COUNT>      Samples per pixel, or on average with --adaptive [default: 1000]
+  -d, --max-depth <COUNT>    Maximum number of rays per path, counting the camera ray
+                             [default: 50]
+      --diffuse-bounces <COUNT>
+      --glossy-bounces <COUNT>
+      --transmission-bounces <COUNT>
+      --volume-bounces <COUNT>
+                             Maximum number of bounces of each kind [default: no limit
+                             besides --max-depth]
+      --roulette-depth <COUNT>
+                             Bounces before paths may be ended by Russian roulette
+                             [default: 3]
+  -o, --output <PATH>        Output image: .png, .jpg, .tif, .webp, .bmp or .ppm, or
+                             .exr, .hdr or .pfm for the linear radiance in floating
+                             point [default: ./render/render<SCENE>.png]
+      --half-float           Write .exr files as 16-bit half floats instead of 32-bit
+      --16-bit               Write .png and .tif files with 16 bits per channel
+  -j, --threads <COUNT>      Number of render threads [default: number of CPUs]
+      --seed <NUMBER>        Seed of the scene generator and sampler [default: random]
+      --filter <NAME>        Pixel filter: box, tent, gaussian, mitchell or lanczos [default: box]
+      --filter-radius <PIXELS>
+                             Filter radius [default: 0.5 box, 1 tent, 1.5 gaussian,
+                             2 mitchell, 3 lanczos]
+      --integrator <NAME>    What to render: path (the image), ao[:DISTANCE] (ambient
+                             occlusion), normals, uv, depth[:FAR] or bvh[:SCALE] (BVH
+                             traversal cost) [default: the scene's, or path]
+      --mis <HEURISTIC>      Weighting of light and BSDF samples: balance or power
+                             [default: power]
+      --adaptive <THRESHOLD> Stop sampling pixels whose relative noise is below THRESHOLD
+                             (e.g. 0.01) and spend the samples saved on noisy pixels
+      --min-samples <COUNT>  Samples every pixel gets with --adaptive [default: 16]
+      --max-samples <COUNT>  Most samples a pixel gets with --adaptive
+                             [default: 4 * samples]
+      --sample-map <PATH>    Also write an image of the samples taken per pixel
+      --tone-map <NAME>      Tone mapping operator: clamp, reinhard, extended-reinhard,
+                             aces or filmic [default: clamp]
+      --exposure <STOPS>     Brighten (or darken, if negative) the image before tone
+                             mapping [default: 0]
+      --white-point <LUMINANCE>
+                             Luminance extended-reinhard maps to white [default: that of
+                             the brightest pixel]
+      --denoise <STRENGTH>   Denoise the image, guided by its albedo, normals and depth;
+                             higher strengths smooth more [recommended: 1]
+      --aov <NAMES>          Also write the comma-separated AOVs albedo, normal, depth,
+                             position and id (material ID), or all, each next to the
+                             output, e.g. render0.albedo.png
+      --progressive          Render in passes over the whole image and write the output
+                             after some of them (implied by the next three options)
+      --pass-samples <COUNT> Samples per pixel in each progressive pass [default: 1]
+      --checkpoint-passes <COUNT>
+                             Write the output every COUNT passes
+      --checkpoint-seconds <SECONDS>
+                             Write the output at most every SECONDS seconds
+                             [default: 60 unless --checkpoint-passes is given]
+      --resume <CHECKPOINT>  Continue the render saved in a checkpoint file; its scene,
+                             output and render settings replace the options above,
+                             except for the threads and checkpoint intervals
+  -h, --help                 Print this help";
+
+pub struct CliArgs {
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u8,
+    pub bounces: BounceLimits,
+    pub roulette_depth: u8,
+    pub output: String,
+    pub threads: usize,
+    pub seed: Option<u64>,
+    pub filter: Filter,
+    pub integrator: Option<IntegratorKind>,
+    pub mis: MisHeuristic,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub sample_map: Option<String>,
+    pub aovs: Vec<Aov>,
+    pub denoiser: Option<Denoiser>,
+    pub image_output: ImageOutput,
+    /// Samples per pass of a progressive render, `None` renders all samples at once.
+    pub pass_samples: Option<u32>,
+    pub checkpoint_passes: Option<u32>,
+    pub checkpoint_seconds: Option<f64>,
+    /// Checkpoint file to continue rendering from.
+    pub resume: Option<String>,
+    pub help: bool,
+}
+
+impl CliArgs {
+    pub fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut scene = None;
+        let mut width = None;
+        let mut height = None;
+        let mut samples_per_pixel: u32 = 1000;
+        let mut max_depth = 50;
+        let mut bounces = BounceLimits::new();
+        let mut roulette_depth = 3;
+        let mut output = None;
+        let mut threads = num_cpus::get();
+        let mut seed = None;
+        let mut filter_kind = FilterKind::Box;
+        let mut filter_radius: Option<f64> = None;
+        let mut integrator = None;
+        let mut mis = MisHeuristic::default();
+        let mut noise_threshold: Option<f64> = None;
+        let mut min_samples = None;
+        let mut max_samples = None;
+        let mut sample_map = None;
+        let mut aovs = vec![];
+        let mut denoise_strength: Option<f64> = None;
+        let mut tone_map = ToneMapOperator::default();
+        let mut exposure: f64 = 0.;
+        let mut white_point: Option<f64> = None;
+        let mut half_float = false;
+        let mut sixteen_bit = false;
+        let mut progressive = false;
+        let mut pass_samples = None;
+        let mut checkpoint_passes = None;
+        let mut checkpoint_seconds: Option<f64> = None;
+        let mut resume = None;
+        let mut help = false;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = |name: &str| {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for '{}'", name))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => help = true,
+                "-s" | "--scene" => scene = Some(value(arg)?),
+                "-W" | "--width" => width = Some(Self::number(arg, &value(arg)?)?),
+                "-H" | "--height" => height = Some(Self::number(arg, &value(arg)?)?),
+                "-n" | "--samples" => samples_per_pixel = Self::number(arg, &value(arg)?)?,
+                "-d" | "--max-depth" => max_depth = Self::number(arg, &value(arg)?)?,
+                "--diffuse-bounces" => bounces.diffuse = Some(Self::number(arg, &value(arg)?)?),
+                "--glossy-bounces" => bounces.glossy = Some(Self::number(arg, &value(arg)?)?),
+                "--transmission-bounces" => {
+                    bounces.transmission = Some(Self::number(arg, &value(arg)?)?)
+                }
+                "--volume-bounces" => bounces.volume = Some(Self::number(arg, &value(arg)?)?),
+                "--roulette-depth" => roulette_depth = Self::number(arg, &value(arg)?)?,
+                "-o" | "--output" => output = Some(value(arg)?),
+                "-j" | "--threads" => threads = Self::number(arg, &value(arg)?)?,
+                "--seed" => seed = Some(Self::number(arg, &value(arg)?)?),
+                "--filter" => filter_kind = value(arg)?.parse()?,
+                "--filter-radius" => filter_radius = Some(Self::number(arg, &value(arg)?)?),
+                "--integrator" => integrator = Some(value(arg)?.parse()?),
+                "--mis" => mis = value(arg)?.parse()?,
+                "--adaptive" => noise_threshold = Some(Self::number(arg, &value(arg)?)?),
+                "--min-samples" => min_samples = Some(Self::number(arg, &value(arg)?)?),
+                "--max-samples" => max_samples = Some(Self::number(arg, &value(arg)?)?),
+                "--sample-map" => sample_map = Some(value(arg)?),
+                "--tone-map" => tone_map = value(arg)?.parse()?,
+                "--exposure" => exposure = Self::number(arg, &value(arg)?)?,
+                "--white-point" => white_point = Some(Self::number(arg, &value(arg)?)?),
+                "--half-float" => half_float = true,
+                "--16-bit" => sixteen_bit = true,
+                "--denoise" => denoise_strength = Some(Self::number(arg, &value(arg)?)?),
+                "--aov" => {
+                    for name in value(arg)?.split(',') {
+                        match name {
+                            "all" => aovs.extend(Aov::ALL),
+                            _ => aovs.push(name.parse()?),
+                        }
+                    }
+                }
+                "--progressive" => progressive = true,
+                "--pass-samples" => pass_samples = Some(Self::number(arg, &value(arg)?)?),
+                "--checkpoint-passes" => checkpoint_passes = Some(Self::number(arg, &value(arg)?)?),
+                "--checkpoint-seconds" => {
+                    checkpoint_seconds = Some(Self::number(arg, &value(arg)?)?)
+                }
+                "--resume" => resume = Some(value(arg)?),
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("unknown option '{}'", arg).into())
+                }
+                _ if scene.is_none() => scene = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument '{}'", arg).into()),
+            }
+        }
+
+        let scene = scene.unwrap_or_else(|| "0".to_string());
+        let width: u32 = width.unwrap_or(1280);
+        let height = height.unwrap_or(width * 9 / 16);
+        if width < 2 || height < 2 {
+            return Err("image must be at least 2x2 pixels".into());
+        }
+        if samples_per_pixel == 0 {
+            return Err("'--samples' must be at least 1".into());
+        }
+        if max_depth == 0 {
+            return Err("'--max-depth' must be at least 1".into());
+        }
+        if threads == 0 {
+            return Err("'--threads' must be at least 1".into());
+        }
+        let mut filter = Filter::new(filter_kind);
+        if let Some(radius) = filter_radius {
+            if !radius.is_finite() || radius <= 0. {
+                return Err("'--filter-radius' must be positive and finite".into());
+            }
+            filter = filter.radius(radius);
+        }
+        let adaptive = match noise_threshold {
+            Some(noise_threshold) => {
+                if noise_threshold.is_nan() || noise_threshold <= 0. {
+                    return Err("'--adaptive' must be positive".into());
+                }
+                let min_samples = min_samples.unwrap_or(16).min(samples_per_pixel);
+                let max_samples = max_samples.unwrap_or(samples_per_pixel.saturating_mul(4));
+                if min_samples == 0 {
+                    return Err("'--min-samples' must be at least 1".into());
+                }
+                if max_samples < samples_per_pixel {
+                    return Err("'--max-samples' must not be less than '--samples'".into());
+                }
+                Some(
+                    AdaptiveSampling::new(noise_threshold)
+                        .min_samples_per_pixel(min_samples)
+                        .max_samples_per_pixel(max_samples),
+                )
+            }
+            None if min_samples.is_some() || max_samples.is_some() => {
+                return Err("'--min-samples' and '--max-samples' need '--adaptive'".into())
+            }
+            None => None,
+        };
+        if denoise_strength.is_some_and(|strength| strength.is_nan() || strength <= 0.) {
+            return Err("'--denoise' must be positive".into());
+        }
+        let denoiser = denoise_strength.map(Denoiser::new);
+        if !exposure.is_finite() {
+            return Err("'--exposure' must be a finite number".into());
+        }
+        let mut tone_mapper = ToneMapper::new(tone_map).exposure(exposure);
+        if let Some(white_point) = white_point {
+            if white_point.is_nan() || white_point <= 0. {
+                return Err("'--white-point' must be positive".into());
+            }
+            if tone_map != ToneMapOperator::ExtendedReinhard {
+                return Err("'--white-point' needs '--tone-map extended-reinhard'".into());
+            }
+            tone_mapper = tone_mapper.white_point(white_point);
+        }
+        if pass_samples == Some(0) {
+            return Err("'--pass-samples' must be at least 1".into());
+        }
+        if checkpoint_passes == Some(0) {
+            return Err("'--checkpoint-passes' must be at least 1".into());
+        }
+        if checkpoint_seconds.is_some_and(|seconds| seconds.is_nan() || seconds < 0.) {
+            return Err("'--checkpoint-seconds' must not be negative".into());
+        }
+        progressive |= pass_samples.is_some()
+            || checkpoint_passes.is_some()
+            || checkpoint_seconds.is_some()
+            || resume.is_some();
+        if progressive && checkpoint_passes.is_none() && checkpoint_seconds.is_none() {
+            checkpoint_seconds = Some(60.);
+        }
+        let pass_samples = progressive.then(|| pass_samples.unwrap_or(1));
+        let output = output.unwrap_or_else(|| Self::default_output(&scene));
+        let format = ImageFormat::from_path(Path::new(&output))?;
+        if sixteen_bit && !format.supports_16_bit() {
+            return Err("'--16-bit' needs a .png or .tif output".into());
+        }
+        if let Some(sample_map) = &sample_map {
+            ImageFormat::from_path(Path::new(sample_map))?;
+        }
+        let image_output = ImageOutput::new()
+            .tone_mapper(tone_mapper)
+            .half_float(half_float)
+            .sixteen_bit(sixteen_bit);
+        Ok(Self {
+            scene,
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            bounces,
+            roulette_depth,
+            output,
+            threads,
+            seed,
+            filter,
+            integrator,
+            mis,
+            adaptive,
+            sample_map,
+            aovs,
+            denoiser,
+            image_output,
+            pass_samples,
+            checkpoint_passes,
+            checkpoint_seconds,
+            resume,
+            help,
+        })
+    }
+
+    fn default_output(scene: &str) -> String {
+        if scene.parse::<u32>().is_ok() {
+            format!("./render/render{}.png", scene)
+        } else {
+            let stem = Path::new(scene)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            format!("./render/render_{}.png", stem)
+        }
+    }
+
+    fn number<N: std::str::FromStr>(name: &str, value: &str) -> Result<N, Box<dyn Error>> {
+        value
+            .parse()
+            .map_err(|_| format!("invalid value '{}' for '{}'", value, name).into())
+    }
+}
@@ -0,0 +1,408 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{aov::Aov, renderer::Framebuffer, vec3::Vec3};
+
+/// Weights of the 5-tap B3 spline each pass blurs with, per axis.
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al., 2010) that removes the
+/// sampling noise left in a render, with the variance-guided edge stopping of SVGF
+/// (Schied et al., 2017).
+///
+/// Every pass blurs with the same 5×5 kernel, its taps spread twice as far apart as in
+/// the pass before, but only across pixels that show the same surface (a similar normal
+/// and depth) and whose brightness differs by no more than the noise explains. The
+/// light reaching the surfaces is filtered apart from their albedo, so textures stay
+/// sharp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// How large brightness differences, relative to the noise of the pixels, are still
+    /// blurred away; 1 by default, higher values give smoother images and softer shadows.
+    pub strength: f64,
+    /// Number of passes; the last one reaches `2^(iterations + 1)` pixels away.
+    pub iterations: u32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            strength: 1.,
+            iterations: 5,
+        }
+    }
+}
+
+impl Denoiser {
+    /// The AOVs `denoise` is guided by.
+    pub const GUIDES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    pub fn new(strength: f64) -> Self {
+        Self {
+            strength,
+            ..Self::default()
+        }
+    }
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Denoises `color` using the albedo, normal and depth AOVs of the same render and
+    /// the variance of every pixel's mean luminance (`Film::luminance_variance`). Where
+    /// that is unknown, it is estimated from the neighbouring pixels.
+    pub fn denoise(
+        &self,
+        color: &Framebuffer,
+        variance: &[Option<f64>],
+        albedo: &Framebuffer,
+        normal: &Framebuffer,
+        depth: &Framebuffer,
+    ) -> Framebuffer {
+        let (width, height) = (color.width as usize, color.height as usize);
+        // Surfaces too dark to divide by keep their color in the lighting.
+        let albedo: Vec<Vec3> = albedo
+            .pixels
+            .iter()
+            .map(|albedo| {
+                let channel = |value: f64| if value > 0.01 { value } else { 1. };
+                Vec3::new(
+                    channel(albedo.x_r),
+                    channel(albedo.y_g),
+                    channel(albedo.z_b),
+                )
+            })
+            .collect();
+        let mut lighting: Vec<Vec3> = color
+            .pixels
+            .iter()
+            .zip(&albedo)
+            .map(|(color, albedo)| {
+                Vec3::new(
+                    color.x_r / albedo.x_r,
+                    color.y_g / albedo.y_g,
+                    color.z_b / albedo.z_b,
+                )
+            })
+            .collect();
+        let mut variance: Vec<f64> = (0..width * height)
+            .map(|index| {
+                let variance = variance[index]
+                    .unwrap_or_else(|| Self::spatial_variance(&lighting, width, height, index));
+                variance / Vec3::luminance(albedo[index]).powi(2)
+            })
+            .collect();
+        let guides = Guides {
+            width,
+            height,
+            // Normals averaged over a pixel are shorter than 1 where they differ.
+            normal: normal
+                .pixels
+                .iter()
+                .map(|&normal| {
+                    if normal == Vec3::default() {
+                        normal
+                    } else {
+                        Vec3::unit_vector(normal)
+                    }
+                })
+                .collect(),
+            depth: depth.pixels.iter().map(|depth| depth.x_r).collect(),
+            depth_gradient: Self::depth_gradient(&depth.pixels, width, height),
+        };
+        for iteration in 0..self.iterations {
+            let deviation: Vec<f64> = (0..width * height)
+                .map(|index| Self::blur_3x3(&variance, width, height, index).sqrt())
+                .collect();
+            let filtered: Vec<(Vec3, f64)> = (0..height)
+                .into_par_iter()
+                .flat_map_iter(|y| {
+                    let (lighting, variance, deviation, guides) =
+                        (&lighting, &variance, &deviation, &guides);
+                    (0..width).map(move |x| {
+                        self.filter_pixel(
+                            x,
+                            y,
+                            1 << iteration,
+                            lighting,
+                            variance,
+                            deviation,
+                            guides,
+                        )
+                    })
+                })
+                .collect();
+            (lighting, variance) = filtered.into_iter().unzip();
+        }
+        let mut framebuffer = Framebuffer::new(color.width, color.height);
+        for (pixel, (lighting, albedo)) in framebuffer
+            .pixels
+            .iter_mut()
+            .zip(lighting.iter().zip(&albedo))
+        {
+            *pixel = *lighting * *albedo;
+        }
+        framebuffer
+    }
+
+    /// Filters the pixel at `(x, y)` with taps `step` pixels apart, giving its lighting
+    /// and the variance of that afterwards.
+    #[allow(clippy::too_many_arguments)]
+    fn filter_pixel(
+        &self,
+        x: usize,
+        y: usize,
+        step: usize,
+        lighting: &[Vec3],
+        variance: &[f64],
+        deviation: &[f64],
+        guides: &Guides,
+    ) -> (Vec3, f64) {
+        let index = y * guides.width + x;
+        let luminance = Vec3::luminance(lighting[index]);
+        let normal = guides.normal[index];
+        let depth = guides.depth[index];
+        // SVGF stops at differences of four standard deviations.
+        let tolerance = 4. * self.strength * deviation[index] + 1e-10;
+        let mut sum = Vec3::default();
+        let mut weight_sum = 0.;
+        let mut variance_sum = 0.;
+        for (j, weight_y) in KERNEL.iter().enumerate() {
+            let dy = (j as isize - 2) * step as isize;
+            let Some(qy) = y.checked_add_signed(dy).filter(|&qy| qy < guides.height) else {
+                continue;
+            };
+            for (i, weight_x) in KERNEL.iter().enumerate() {
+                let dx = (i as isize - 2) * step as isize;
+                let Some(qx) = x.checked_add_signed(dx).filter(|&qx| qx < guides.width) else {
+                    continue;
+                };
+                let q = qy * guides.width + qx;
+                let luminance_weight =
+                    (-(luminance - Vec3::luminance(lighting[q])).abs() / tolerance).exp();
+                let normal_weight = if normal == guides.normal[q] {
+                    1.
+                } else {
+                    Vec3::dot(normal, guides.normal[q]).max(0.).powi(64)
+                };
+                // Depth may change as fast along the offset as it does between
+                // neighbouring pixels.
+                let offset = ((dx * dx + dy * dy) as f64).sqrt();
+                let depth_weight = (-(depth - guides.depth[q]).abs()
+                    / (guides.depth_gradient[index] * offset + 1e-3 * depth.abs() + 1e-10))
+                    .exp();
+                let weight = weight_x * weight_y * luminance_weight * normal_weight * depth_weight;
+                sum += lighting[q] * weight;
+                weight_sum += weight;
+                variance_sum += weight * weight * variance[q];
+            }
+        }
+        (sum / weight_sum, variance_sum / (weight_sum * weight_sum))
+    }
+
+    /// Variance of the luminance of the pixel and its 3×3 neighbourhood, for pixels with
+    /// too few samples to tell their own.
+    fn spatial_variance(lighting: &[Vec3], width: usize, height: usize, index: usize) -> f64 {
+        let (x, y) = (index % width, index / width);
+        let mut count = 0.;
+        let mut sum = 0.;
+        let mut square_sum = 0.;
+        for j in y.saturating_sub(1)..(y + 2).min(height) {
+            for i in x.saturating_sub(1)..(x + 2).min(width) {
+                let luminance = Vec3::luminance(lighting[j * width + i]);
+                count += 1.;
+                sum += luminance;
+                square_sum += luminance * luminance;
+            }
+        }
+        (square_sum / count - (sum / count).powi(2)).max(0.)
+    }
+
+    fn blur_3x3(values: &[f64], width: usize, height: usize, index: usize) -> f64 {
+        const WEIGHTS: [f64; 3] = [0.25, 0.5, 0.25];
+        let (x, y) = (index % width, index / width);
+        let mut sum = 0.;
+        let mut weight_sum = 0.;
+        for (j, weight_y) in WEIGHTS.iter().enumerate() {
+            let Some(qy) = (y + j).checked_sub(1).filter(|&qy| qy < height) else {
+                continue;
+            };
+            for (i, weight_x) in WEIGHTS.iter().enumerate() {
+                let Some(qx) = (x + i).checked_sub(1).filter(|&qx| qx < width) else {
+                    continue;
+                };
+                sum += weight_x * weight_y * values[qy * width + qx];
+                weight_sum += weight_x * weight_y;
+            }
+        }
+        sum / weight_sum
+    }
+
+    /// How much the depth changes from one pixel to the next, along the steeper axis.
+    /// The smaller difference on either side is taken, so that silhouettes, where the
+    /// depth jumps, do not count.
+    fn depth_gradient(depth: &[Vec3], width: usize, height: usize) -> Vec<f64> {
+        let at = |x: usize, y: usize| depth[y * width + x].x_r;
+        (0..width * height)
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let center = at(x, y);
+                let slope = |before: Option<f64>, after: Option<f64>| {
+                    [before, after]
+                        .into_iter()
+                        .flatten()
+                        .map(|value| (value - center).abs())
+                        .fold(f64::MAX, f64::min)
+                };
+                let slope_x = slope(
+                    x.checked_sub(1).map(|x| at(x, y)),
+                    (x + 1 < width).then(|| at(x + 1, y)),
+                );
+                let slope_y = slope(
+                    y.checked_sub(1).map(|y| at(x, y)),
+                    (y + 1 < height).then(|| at(x, y + 1)),
+                );
+                slope_x.max(slope_y)
+            })
+            .collect()
+    }
+}
+
+/// What tells the surfaces in the image apart.
+struct Guides {
+    width: usize,
+    height: usize,
+    normal: Vec<Vec3>,
+    depth: Vec<f64>,
+    depth_gradient: Vec<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use crate::random::{Random, SampleRng};
+
+    use super::*;
+
+    const SIZE: u32 = 16;
+
+    fn image(pixel: impl Fn(u32, u32) -> Vec3) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(SIZE, SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                framebuffer.put_pixel(x, y, pixel(x, y));
+            }
+        }
+        framebuffer
+    }
+
+    /// Lighting of `value` times 1 ± `amount`, different in every pixel.
+    fn noisy(value: impl Fn(u32, u32) -> Vec3, amount: f64) -> Framebuffer {
+        let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        let noise: Vec<f64> = (0..SIZE * SIZE)
+            .map(|_| random.random(Some(-amount), Some(amount)))
+            .collect();
+        image(|x, y| value(x, y) * (1. + noise[(y * SIZE + x) as usize]))
+    }
+
+    fn assert_close(a: Vec3, b: Vec3, tolerance: f64) {
+        assert!(Vec3::length(a - b) < tolerance, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn constant_images_come_back_unchanged() {
+        let color = Vec3::new(0.3, 0.5, 0.7);
+        let albedo = image(|_, _| Vec3::new(0.5, 0.6, 0.7));
+        let normal = image(|_, _| Vec3::new(0., 0., 1.));
+        let depth = image(|_, _| Vec3::new(2., 2., 2.));
+        for variance in [None, Some(0.01)] {
+            let variance = vec![variance; (SIZE * SIZE) as usize];
+            let denoised = Denoiser::default().denoise(
+                &image(|_, _| color),
+                &variance,
+                &albedo,
+                &normal,
+                &depth,
+            );
+            for pixel in denoised.pixels {
+                assert_close(pixel, color, 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn albedo_and_normal_edges_are_kept() {
+        let left = |x: u32| x < SIZE / 2;
+        let flat = image(|_, _| Vec3::new(0., 0., 1.));
+        let depth = image(|_, _| Vec3::new(2., 2., 2.));
+        let variance = vec![Some(0.01); (SIZE * SIZE) as usize];
+        let denoiser = Denoiser::new(10.);
+
+        // A red and a blue surface, lit the same.
+        let red = Vec3::new(0.8, 0.1, 0.1);
+        let blue = Vec3::new(0.1, 0.1, 0.8);
+        let albedo = image(|x, _| if left(x) { red } else { blue });
+        let color = noisy(|x, _| if left(x) { red } else { blue }, 0.2);
+        let denoised = denoiser.denoise(&color, &variance, &albedo, &flat, &depth);
+        for y in 0..SIZE {
+            assert_close(denoised.get_pixel(SIZE / 2 - 1, y), red, 0.1);
+            assert_close(denoised.get_pixel(SIZE / 2, y), blue, 0.1);
+        }
+
+        // A bright and a dark wall meeting at a right angle.
+        let white = image(|_, _| Vec3::new(1., 1., 1.));
+        let normal = image(|x, _| {
+            if left(x) {
+                Vec3::new(0., 0., 1.)
+            } else {
+                Vec3::new(1., 0., 0.)
+            }
+        });
+        let lit = |x| if left(x) { 1. } else { 0.2 };
+        let color = noisy(|x, _| Vec3::new(1., 1., 1.) * lit(x), 0.2);
+        let denoised = denoiser.denoise(&color, &variance, &white, &normal, &depth);
+        let error = |framebuffer: &Framebuffer| {
+            (0..SIZE * SIZE)
+                .map(|index| {
+                    let x = index % SIZE;
+                    (Vec3::luminance(framebuffer.pixels[index as usize]) - lit(x)).powi(2)
+                })
+                .sum::<f64>()
+        };
+        assert!(error(&denoised) < error(&color) / 4.);
+        for y in 0..SIZE {
+            assert!((Vec3::luminance(denoised.get_pixel(SIZE / 2 - 1, y)) - 1.).abs() < 0.1);
+            assert!((Vec3::luminance(denoised.get_pixel(SIZE / 2, y)) - 0.2).abs() < 0.05);
+        }
+
+        // The same walls filtered without the normals do bleed into each other.
+        let denoised = denoiser.denoise(&color, &variance, &white, &flat, &depth);
+        assert!(Vec3::luminance(denoised.get_pixel(SIZE / 2, 0)) > 0.3);
+    }
+
+    #[test]
+    fn strength_zero_keeps_every_pixel() {
+        let mut random = Random::new(SampleRng::new(1), Uniform::new(0., 1.));
+        let mut random_image = || {
+            let pixels: Vec<Vec3> = (0..SIZE * SIZE)
+                .map(|_| {
+                    Vec3::new(
+                        random.random(None, None),
+                        random.random(None, None),
+                        random.random(None, None),
+                    )
+                })
+                .collect();
+            image(|x, y| pixels[(y * SIZE + x) as usize])
+        };
+        let color = random_image();
+        let albedo = random_image();
+        let normal = image(|_, _| Vec3::new(0., 0., 1.));
+        let depth = image(|_, _| Vec3::new(2., 2., 2.));
+        let variance = vec![None; (SIZE * SIZE) as usize];
+        let denoised = Denoiser::new(0.).denoise(&color, &variance, &albedo, &normal, &depth);
+        for (denoised, color) in denoised.pixels.iter().zip(&color.pixels) {
+            assert_close(*denoised, *color, 1e-9);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{filter::Filter, renderer::Framebuffer, vec3::Vec3};
+
+/// Pixels `x0..x1` × `y0..y1` of the image, rendered as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileBounds {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl TileBounds {
+    pub fn pixel_count(&self) -> u64 {
+        (self.x1 - self.x0) as u64 * (self.y1 - self.y0) as u64
+    }
+}
+
+/// Running statistics of the samples taken inside one pixel, before filtering.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelStats {
+    pub count: u32,
+    pub luminance_sum: f64,
+    pub luminance_square_sum: f64,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, radiance: Vec3) {
+        let luminance = Vec3::luminance(radiance);
+        self.count += 1;
+        self.luminance_sum += luminance;
+        self.luminance_square_sum += luminance * luminance;
+    }
+
+    pub fn merge(&mut self, other: &PixelStats) {
+        self.count += other.count;
+        self.luminance_sum += other.luminance_sum;
+        self.luminance_square_sum += other.luminance_square_sum;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.;
+        }
+        self.luminance_sum / self.count as f64
+    }
+
+    /// Unbiased sample variance of the luminance.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.;
+        }
+        let n = self.count as f64;
+        ((self.luminance_square_sum - self.luminance_sum * self.luminance_sum / n) / (n - 1.))
+            .max(0.)
+    }
+
+    /// Standard error of the mean luminance relative to its square root, so that dark
+    /// pixels, where the eye is more sensitive, converge to a smaller absolute error
+    /// without needing the same relative one.
+    pub fn error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let standard_error = (self.variance() / self.count as f64).sqrt();
+        standard_error / self.mean().max(1e-8).sqrt()
+    }
+}
+
+/// Samples of one tile, accumulated privately by a worker and then merged into the `Film`.
+///
+/// Samples are taken inside `bounds`, but the filter spreads them into neighbouring
+/// pixels, so the tile stores the larger `pixel_bounds`.
+pub struct FilmTile {
+    pub bounds: TileBounds,
+    pub pixel_bounds: TileBounds,
+    filter: Filter,
+    pixels: Vec<Vec3>,
+    weights: Vec<f64>,
+    stats: Vec<PixelStats>,
+}
+
+impl FilmTile {
+    /// Creates an empty tile for the samples inside `bounds`, big enough to take
+    /// everything `filter` splats around them without leaving the film.
+    pub fn new(bounds: TileBounds, filter: Filter, film_width: u32, film_height: u32) -> Self {
+        // No filter reaches further than across the whole film.
+        let padding = (filter.radius - 0.5)
+            .ceil()
+            .clamp(0., film_width.max(film_height) as f64) as u32;
+        let pixel_bounds = TileBounds {
+            x0: bounds.x0.saturating_sub(padding),
+            y0: bounds.y0.saturating_sub(padding),
+            x1: bounds.x1.saturating_add(padding).min(film_width),
+            y1: bounds.y1.saturating_add(padding).min(film_height),
+        };
+        let count = pixel_bounds.pixel_count() as usize;
+        Self {
+            bounds,
+            pixel_bounds,
+            filter,
+            pixels: vec![Vec3::default(); count],
+            weights: vec![0.; count],
+            stats: vec![PixelStats::default(); bounds.pixel_count() as usize],
+        }
+    }
+
+    /// Splats a radiance sample of `pixel` taken at continuous image position `(x, y)`,
+    /// where pixel `(i, j)` covers `[i, i + 1) × [j, j + 1)`.
+    pub fn add_sample(&mut self, pixel: (u32, u32), (x, y): (f64, f64), radiance: Vec3) {
+        let radius = self.filter.radius;
+        let bounds = self.pixel_bounds;
+        // Pixels whose centers lie within the filter radius.
+        let x0 = ((x - 0.5 - radius).ceil().max(bounds.x0 as f64)) as u32;
+        let x1 = ((x - 0.5 + radius).floor() + 1.).min(bounds.x1 as f64) as u32;
+        let y0 = ((y - 0.5 - radius).ceil().max(bounds.y0 as f64)) as u32;
+        let y1 = ((y - 0.5 + radius).floor() + 1.).min(bounds.y1 as f64) as u32;
+        let tile_width = bounds.x1 - bounds.x0;
+        let stats_index = (pixel.1 - self.bounds.y0) * (self.bounds.x1 - self.bounds.x0)
+            + (pixel.0 - self.bounds.x0);
+        self.stats[stats_index as usize].add(radiance);
+        for j in y0..y1 {
+            for i in x0..x1 {
+                let weight = self.filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if weight == 0. {
+                    continue;
+                }
+                let index = ((j - bounds.y0) * tile_width + (i - bounds.x0)) as usize;
+                self.pixels[index] += radiance * weight;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    /// Number of samples taken in the tile.
+    pub fn sample_count(&self) -> u64 {
+        self.stats.iter().map(|stats| stats.count as u64).sum()
+    }
+}
+
+/// Floating-point accumulation buffer: the weighted sum of all radiance samples, the
+/// sum of their weights and the statistics of the samples taken per pixel, row by
+/// row starting at the top-left corner.
+#[derive(Clone, Debug)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub(crate) pixels: Vec<Vec3>,
+    pub(crate) weights: Vec<f64>,
+    pub(crate) stats: Vec<PixelStats>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        let count = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            pixels: vec![Vec3::default(); count],
+            weights: vec![0.; count],
+            stats: vec![PixelStats::default(); count],
+        }
+    }
+
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Splits the image into tiles of at most `tile_size` × `tile_size` pixels, row by row.
+    pub fn tiles(&self, tile_size: u32) -> Vec<TileBounds> {
+        let mut tiles = vec![];
+        for y0 in (0..self.height).step_by(tile_size as usize) {
+            for x0 in (0..self.width).step_by(tile_size as usize) {
+                tiles.push(TileBounds {
+                    x0,
+                    y0,
+                    x1: (x0 + tile_size).min(self.width),
+                    y1: (y0 + tile_size).min(self.height),
+                });
+            }
+        }
+        tiles
+    }
+
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        let bounds = tile.pixel_bounds;
+        let tile_width = (bounds.x1 - bounds.x0) as usize;
+        for y in bounds.y0..bounds.y1 {
+            let film_row = (y * self.width + bounds.x0) as usize;
+            let tile_row = (y - bounds.y0) as usize * tile_width;
+            for i in 0..tile_width {
+                self.pixels[film_row + i] += tile.pixels[tile_row + i];
+                self.weights[film_row + i] += tile.weights[tile_row + i];
+            }
+        }
+        let bounds = tile.bounds;
+        let tile_width = (bounds.x1 - bounds.x0) as usize;
+        for y in bounds.y0..bounds.y1 {
+            let film_row = (y * self.width + bounds.x0) as usize;
+            let tile_row = (y - bounds.y0) as usize * tile_width;
+            for i in 0..tile_width {
+                self.stats[film_row + i].merge(&tile.stats[tile_row + i]);
+            }
+        }
+    }
+
+    pub fn pixel_stats(&self, x: u32, y: u32) -> PixelStats {
+        self.stats[(y * self.width + x) as usize]
+    }
+
+    /// Number of samples taken in the whole image.
+    pub fn sample_count(&self) -> u64 {
+        self.stats.iter().map(|stats| stats.count as u64).sum()
+    }
+
+    /// Variance of the mean luminance of every pixel's samples, which shrinks as more
+    /// samples are taken; `None` for pixels with fewer than two samples.
+    pub fn luminance_variance(&self) -> Vec<Option<f64>> {
+        self.stats
+            .iter()
+            .map(|stats| (stats.count >= 2).then(|| stats.variance() / stats.count as f64))
+            .collect()
+    }
+
+    /// Gray image of the number of samples taken per pixel, from black for none up to
+    /// white for the most sampled pixel.
+    pub fn sample_map(&self) -> Framebuffer {
+        let max_count = self
+            .stats
+            .iter()
+            .map(|stats| stats.count)
+            .max()
+            .unwrap_or(0);
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        if max_count > 0 {
+            for (pixel, stats) in framebuffer.pixels.iter_mut().zip(&self.stats) {
+                let value = stats.count as f64 / max_count as f64;
+                *pixel = Vec3::new(value, value, value);
+            }
+        }
+        framebuffer
+    }
+
+    /// Resolves the film into the weighted average radiance; pixels without samples
+    /// (or with filter weights cancelling out) stay black.
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        for (i, (pixel, weight)) in self.pixels.iter().zip(&self.weights).enumerate() {
+            if *weight > 0. {
+                framebuffer.pixels[i] = *pixel / *weight;
+            }
+        }
+        framebuffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    /// Splats `radiance` at a 4×4 grid of positions in every pixel of a 12×10 film.
+    fn splat_constant(filter: Filter, radiance: Vec3) -> Film {
+        let mut film = Film::new(12, 10);
+        for bounds in film.tiles(5) {
+            let mut tile = FilmTile::new(bounds, filter, film.width, film.height);
+            for y in bounds.y0..bounds.y1 {
+                for x in bounds.x0..bounds.x1 {
+                    for k in 0..16 {
+                        let offset = |k: u32| (k as f64 + 0.5) / 4.;
+                        let position = (x as f64 + offset(k % 4), y as f64 + offset(k / 4));
+                        tile.add_sample((x, y), position, radiance);
+                    }
+                }
+            }
+            film.merge_tile(&tile);
+        }
+        film
+    }
+
+    #[test]
+    fn filter_weights_are_normalized() {
+        let radiance = Vec3::new(0.25, 1., 4.);
+        for kind in [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            for radius in [kind.default_radius(), 0.8, 2.5] {
+                let film = splat_constant(Filter::new(kind).radius(radius), radiance);
+                assert_eq!(film.sample_count(), 12 * 10 * 16);
+                for pixel in film.to_framebuffer().pixels {
+                    assert!(
+                        Vec3::length(pixel - radiance) < 1e-9,
+                        "{} filter of radius {} resolved {:?}",
+                        kind,
+                        radius,
+                        pixel
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn huge_filters_stay_within_the_film() {
+        let filter = Filter::new(FilterKind::Tent).radius(1e10);
+        let bounds = TileBounds {
+            x0: 4,
+            y0: 2,
+            x1: 8,
+            y1: 6,
+        };
+        let mut tile = FilmTile::new(bounds, filter, 12, 10);
+        assert_eq!(
+            tile.pixel_bounds,
+            TileBounds {
+                x0: 0,
+                y0: 0,
+                x1: 12,
+                y1: 10,
+            }
+        );
+        tile.add_sample((5, 3), (5.5, 3.5), Vec3::new(1., 1., 1.));
+        let mut film = Film::new(12, 10);
+        film.merge_tile(&tile);
+        assert!(film.weights.iter().all(|&weight| weight > 0.));
+    }
+}
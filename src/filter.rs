@@ -0,0 +1,130 @@
+use std::{error::Error, f64::consts::PI, fmt::Display, str::FromStr};
+
+/// Shape of the pixel reconstruction filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    /// Mitchell–Netravali with B = C = 1/3.
+    Mitchell,
+    /// Sinc windowed by a sinc that is as wide as the filter.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.,
+            FilterKind::Lanczos => 3.,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "box" => Ok(FilterKind::Box),
+            "tent" | "triangle" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            _ => Err(format!("unknown filter '{}'", name).into()),
+        }
+    }
+}
+
+impl Display for FilterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Separable reconstruction filter used to splat samples into the film.
+///
+/// Every sample contributes to all pixels whose center lies within `radius`
+/// pixels of it (on both axes), weighted by the filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::Box)
+    }
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+    pub fn radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Weight of a sample at offset `(dx, dy)` pixels from a pixel center.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, d: f64) -> f64 {
+        let r = self.radius;
+        let d = d.abs();
+        if d > r {
+            return 0.;
+        }
+        match self.kind {
+            FilterKind::Box => 1.,
+            FilterKind::Tent => r - d,
+            FilterKind::Gaussian => {
+                let sigma = r / 3.;
+                let gaussian = |x: f64| (-x * x / (2. * sigma * sigma)).exp();
+                // Shifted down so that the filter reaches zero at its radius.
+                (gaussian(d) - gaussian(r)).max(0.)
+            }
+            FilterKind::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                let x = 2. * d / r;
+                if x > 1. {
+                    ((-b - 6. * c) * x.powi(3)
+                        + (6. * b + 30. * c) * x.powi(2)
+                        + (-12. * b - 48. * c) * x
+                        + (8. * b + 24. * c))
+                        / 6.
+                } else {
+                    ((12. - 9. * b - 6. * c) * x.powi(3)
+                        + (-18. + 12. * b + 6. * c) * x.powi(2)
+                        + (6. - 2. * b))
+                        / 6.
+                }
+            }
+            FilterKind::Lanczos => Self::sinc(d) * Self::sinc(d / r),
+        }
+    }
+
+    fn sinc(x: f64) -> f64 {
+        if x < 1e-5 {
+            1.
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::{aabb::Aabb, objects::Hittable};
 
-use crate::{material::Material, ray::Ray, vec3::Vec3};
+use crate::{material::Material, random::Random, ray::Ray, vec3::Vec3};
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Vec3,
@@ -12,6 +12,8 @@ pub struct HitRecord {
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    /// Interpolated vertex color, for meshes that have one.
+    pub color: Option<Vec3>,
 }
 
 impl HitRecord {
@@ -24,6 +26,21 @@ impl HitRecord {
             -outward_normal
         };
     }
+
+    /// Ray leaving the hit point in `direction`. Its origin is pushed off the surface,
+    /// to the side `direction` points to, by a distance that grows with the hit
+    /// point's coordinates, so that rounding errors in `p` can't make the ray hit the
+    /// surface it leaves.
+    pub fn spawn_ray(&self, direction: Vec3, time: f64) -> Ray {
+        let scale = self.p.x_r.abs().max(self.p.y_g.abs()).max(self.p.z_b.abs());
+        let offset = self.normal * (1e-9 * (1. + scale));
+        let origin = if Vec3::dot(direction, self.normal) < 0. {
+            self.p - offset
+        } else {
+            self.p + offset
+        };
+        Ray::new(origin, direction, time)
+    }
 }
 impl Default for HitRecord {
     fn default() -> Self {
@@ -35,6 +52,7 @@ impl Default for HitRecord {
             u: 0.0,
             v: 0.0,
             front_face: false,
+            color: None,
         }
     }
 }
@@ -43,12 +61,12 @@ pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
 }
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
         let mut hit_record = None;
         let mut closest_so_far = t_max;
 
         for object in self.objects.iter() {
-            if let Some(rec) = object.hit(ray, t_min, closest_so_far) {
+            if let Some(rec) = object.hit(ray, t_min, closest_so_far, rng) {
                 closest_so_far = rec.t;
                 hit_record = Some(rec);
             }
@@ -57,6 +75,30 @@ impl Hittable for HittableList {
         hit_record
     }
 
+    /// Picks one of the objects with equal probability.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|object| object.pdf_value(origin, direction, rng))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        let index = (rng.random(None, None) * self.objects.len() as f64) as usize;
+        self.objects[index.min(self.objects.len() - 1)].random(origin, rng)
+    }
+
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        for object in &self.objects {
+            object.clone().collect_lights(lights);
+        }
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool {
         if self.objects.is_empty() {
             return false;
@@ -81,6 +123,17 @@ impl HittableList {
     pub fn new(objects: Vec<Arc<dyn Hittable>>) -> Self {
         Self { objects }
     }
+
+    /// The emissive objects in the list, for sampling light directly: spheres,
+    /// rectangles (also as sides of boxes), triangles and meshes, also inside BVHs and
+    /// transforms. Moving spheres and media are not sampled.
+    pub fn lights(&self) -> HittableList {
+        let mut lights = vec![];
+        for object in &self.objects {
+            object.clone().collect_lights(&mut lights);
+        }
+        HittableList::new(lights)
+    }
 }
 
 impl<T: Hittable + ?Sized> Hittable for Option<Arc<T>> {
@@ -92,11 +145,125 @@ impl<T: Hittable + ?Sized> Hittable for Option<Arc<T>> {
         }
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
         if let Some(obj) = self {
-            obj.hit(ray, t_min, t_max)
+            obj.hit(ray, t_min, t_max, rng)
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        bvh_node::BvhNode,
+        material::{diffuse_light::DiffuseLight, lamberian::Lamberian},
+        objects::{
+            rect::XzRect, sphere::Sphere, translate::Translate, triangle::Triangle,
+            triangle_mesh::TriangleMesh, y_rotation::YRotation,
+        },
+        random::SampleRng,
+        texture::solid_color::SolidColor,
+    };
+
+    fn light() -> Arc<dyn Material> {
+        DiffuseLight::new(SolidColor::new(Vec3::new(4., 4., 4.)))
+    }
+
+    /// Square pyramid without a base, open towards -y.
+    fn pyramid(material: Arc<dyn Material>) -> Arc<TriangleMesh> {
+        let positions = vec![
+            Vec3::new(-0.5, 0., -0.5),
+            Vec3::new(0.5, 0., -0.5),
+            Vec3::new(0.5, 0., 0.5),
+            Vec3::new(-0.5, 0., 0.5),
+            Vec3::new(0., 0.7, 0.),
+        ];
+        let indices = vec![[0, 1, 4], [1, 2, 4], [2, 3, 4], [3, 0, 4]];
+        TriangleMesh::new(positions, None, None, None, indices, material)
+    }
+
+    #[test]
+    fn lights_are_found_once_through_bvhs_and_transforms() {
+        let diffuse = Lamberian::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut single: Vec<Arc<dyn Hittable>> =
+            vec![Sphere::new(Vec3::new(0., 2., 0.), 0.5, light())];
+        let mut objects: Vec<Arc<dyn Hittable>> = vec![
+            Arc::new(BvhNode::new(&mut single, 0., 1.)),
+            Translate::new(
+                XzRect::new(-1., 1., -1., 1., 0., light()),
+                Vec3::new(0., 3., 0.),
+            ),
+            YRotation::new(pyramid(light()), 30.),
+            Triangle::new(
+                Vec3::new(0., 0., 0.),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                light(),
+            ),
+            pyramid(diffuse.clone()),
+            Sphere::new(Vec3::new(0., -100., 0.), 99., diffuse),
+        ];
+        let world = HittableList::new(vec![Arc::new(BvhNode::new(&mut objects, 0., 1.))]);
+        assert_eq!(world.lights().objects.len(), 4);
+    }
+
+    #[test]
+    fn light_pdfs_integrate_to_one() {
+        let lights: [(&str, Arc<dyn Hittable>); 3] = [
+            (
+                "rotated rectangle",
+                YRotation::new(
+                    Translate::new(
+                        XzRect::new(-0.5, 0.5, -0.2, 0.2, 0., light()),
+                        Vec3::new(0.4, 0.6, 0.),
+                    ),
+                    40.,
+                ),
+            ),
+            (
+                "triangle",
+                Triangle::new(
+                    Vec3::new(-0.5, 0.4, -0.5),
+                    Vec3::new(0.8, 0.5, 0.),
+                    Vec3::new(0., 0.7, 0.6),
+                    light(),
+                ),
+            ),
+            (
+                "mesh",
+                Translate::new(pyramid(light()), Vec3::new(0.1, 0.3, -0.2)),
+            ),
+        ];
+        let origin = Vec3::default();
+        let mut rng = Random::new(SampleRng::new(5), Uniform::new(0., 1.));
+        for (name, light) in lights {
+            let samples = 400_000;
+            let mut total = 0.;
+            for _ in 0..samples {
+                let z = 1. - 2. * rng.random(None, None);
+                let phi = 2. * PI * rng.random(None, None);
+                let r = (1. - z * z).max(0.).sqrt();
+                let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                total += light.pdf_value(origin, direction, &mut rng) * 4. * PI;
+            }
+            total /= samples as f64;
+            assert!(
+                (total - 1.).abs() < 0.02,
+                "{}: pdf integrates to {}",
+                name,
+                total
+            );
+            for _ in 0..1000 {
+                let direction = light.random(origin, &mut rng);
+                assert!(light.pdf_value(origin, direction, &mut rng) > 0.);
+            }
+        }
+    }
+}
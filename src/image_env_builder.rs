@@ -1,4 +1,5 @@
 use crate::{
+    background::Background,
     bvh_node::BvhNode,
     camera::Camera,
     hittable::HittableList,
@@ -10,38 +11,39 @@ use crate::{
         box_render::BoxRender,
         constant_medium::ConstantMedium,
         moving_sphere::MovingSphere,
-        rect::{xy_rect, xz_rect, yz_rect},
+        rect::{XyRect, XzRect, YzRect},
         sphere::Sphere,
         translate::Translate,
         y_rotation::YRotation,
     },
     random::Random,
+    scene::Scene,
     texture::{
         checker_texture::CheckerTexture, image_texture::ImageTexture, noise_texture::NoiseTexture,
         solid_color::SolidColor,
     },
     vec3::Vec3,
-    Hittable, ASPECT_RATIO,
+    Hittable,
 };
 use std::{path::Path, sync::Arc};
 
 pub struct ImageEnvBuilder {}
 
 impl ImageEnvBuilder {
-    pub fn build(choice: u32, random: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn build(choice: u32, aspect_ratio: f64, random: &mut Random<f64>) -> Scene {
         match choice {
-            0 => Self::randon_scene(random),
-            1 => Self::two_spheres(random),
-            2 => Self::two_perlin_spheres(random),
-            3 => Self::earth(),
-            4 => Self::simple_light(random),
-            5 => Self::cornell_box(),
-            6 => Self::cornell_smoke(),
-            7 => Self::final_scene(random),
-            _ => Self::randon_scene(random),
+            0 => Self::randon_scene(aspect_ratio, random),
+            1 => Self::two_spheres(aspect_ratio, random),
+            2 => Self::two_perlin_spheres(aspect_ratio, random),
+            3 => Self::earth(aspect_ratio),
+            4 => Self::simple_light(aspect_ratio, random),
+            5 => Self::cornell_box(aspect_ratio),
+            6 => Self::cornell_smoke(aspect_ratio),
+            7 => Self::final_scene(aspect_ratio, random),
+            _ => Self::randon_scene(aspect_ratio, random),
         }
     }
-    pub fn randon_scene(rng: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn randon_scene(aspect_ratio: f64, rng: &mut Random<f64>) -> Scene {
         let mut world = HittableList::new(vec![]);
 
         let checker = CheckerTexture::new(
@@ -51,7 +53,7 @@ impl ImageEnvBuilder {
         world.objects.push(Sphere::new(
             Vec3::new(0., -1000., 0.),
             1000.,
-            DiffuseLight::new(checker),
+            Lamberian::new(checker),
         ));
         for a in -11..11 {
             for b in -11..11 {
@@ -116,15 +118,15 @@ impl ImageEnvBuilder {
             Vec3::new(0., 0., 0.),
             Vec3::new(0., 1., 0.),
             10.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world)).background(Background::sky())
     }
 
-    pub fn two_spheres(_rng: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn two_spheres(aspect_ratio: f64, _rng: &mut Random<f64>) -> Scene {
         let mut world = HittableList::new(vec![]);
         let checker = CheckerTexture::new(
             SolidColor::new(Vec3::new(0.2, 0.3, 0.1)),
@@ -138,7 +140,7 @@ impl ImageEnvBuilder {
         world.objects.push(Sphere::new(
             Vec3::new(0., 10., 0.),
             10.,
-            DiffuseLight::new(checker),
+            Lamberian::new(checker),
         ));
         let mut return_world = HittableList::new(vec![]);
         return_world
@@ -149,21 +151,22 @@ impl ImageEnvBuilder {
             Vec3::new(0., 0., 0.),
             Vec3::new(0., 1., 0.),
             20.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
+            .background(Background::Solid(Vec3::new(0.7, 0.8, 1.)))
     }
 
-    pub fn two_perlin_spheres(rng: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn two_perlin_spheres(aspect_ratio: f64, rng: &mut Random<f64>) -> Scene {
         let mut world = HittableList::new(vec![]);
         let pertext = Arc::new(NoiseTexture::new(rng, 4.0));
         world.objects.push(Sphere::new(
             Vec3::new(0., -1000., 0.),
             1000.,
-            DiffuseLight::new(pertext.clone()),
+            Lamberian::new(pertext.clone()),
         ));
         world.objects.push(Sphere::new(
             Vec3::new(0., 2., 0.),
@@ -179,18 +182,19 @@ impl ImageEnvBuilder {
             Vec3::new(0., 0., 0.),
             Vec3::new(0., 1., 0.),
             20.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
+            .background(Background::Solid(Vec3::new(0.7, 0.8, 1.)))
     }
 
-    pub fn earth() -> (Camera, Arc<HittableList>) {
+    pub fn earth(aspect_ratio: f64) -> Scene {
         let mut world = HittableList::new(vec![]);
         if let Ok(earth_texture) = ImageTexture::new(Path::new("earthmap.jpg")) {
-            let earth_surface = DiffuseLight::new(earth_texture);
+            let earth_surface = Lamberian::new(earth_texture);
             let globe = Sphere::new(Vec3::new(0., 0., 0.), 2., earth_surface);
             world.objects.push(globe);
         }
@@ -203,15 +207,16 @@ impl ImageEnvBuilder {
             Vec3::new(0., 0., 0.),
             Vec3::new(0., 1., 0.),
             20.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
+            .background(Background::Solid(Vec3::new(0.7, 0.8, 1.)))
     }
 
-    pub fn simple_light(rng: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn simple_light(aspect_ratio: f64, rng: &mut Random<f64>) -> Scene {
         let mut world = HittableList::new(vec![]);
         let pertext = Arc::new(NoiseTexture::new(rng, 4.0));
         world.objects.push(Sphere::new(
@@ -228,7 +233,7 @@ impl ImageEnvBuilder {
         let difflight = DiffuseLight::new(SolidColor::new(Vec3::new(4., 4., 4.)));
         world
             .objects
-            .push(xy_rect::new(3., 5., 1., 3., -2., difflight));
+            .push(XyRect::new(3., 5., 1., 3., -2., difflight));
 
         let mut return_world = HittableList::new(vec![]);
         return_world
@@ -239,15 +244,15 @@ impl ImageEnvBuilder {
             Vec3::new(0., 2., 0.),
             Vec3::new(0., 1., 0.),
             20.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
     }
 
-    pub fn cornell_box() -> (Camera, Arc<HittableList>) {
+    pub fn cornell_box(aspect_ratio: f64) -> Scene {
         let mut world = HittableList::new(vec![]);
 
         let red = Lamberian::new(SolidColor::new(Vec3::new(0.65, 0.05, 0.05)));
@@ -257,22 +262,20 @@ impl ImageEnvBuilder {
 
         world
             .objects
-            .push(yz_rect::new(0., 555., 0., 555., 555., green));
-        world
-            .objects
-            .push(yz_rect::new(0., 555., 0., 555., 0., red));
+            .push(YzRect::new(0., 555., 0., 555., 555., green));
+        world.objects.push(YzRect::new(0., 555., 0., 555., 0., red));
         world
             .objects
-            .push(xz_rect::new(213., 343., 227., 332., 554., light));
+            .push(XzRect::new(213., 343., 227., 332., 554., light));
         world
             .objects
-            .push(xz_rect::new(0., 555., 0., 555., 0., white.clone()));
+            .push(XzRect::new(0., 555., 0., 555., 0., white.clone()));
         world
             .objects
-            .push(xz_rect::new(0., 555., 0., 555., 555., white.clone()));
+            .push(XzRect::new(0., 555., 0., 555., 555., white.clone()));
         world
             .objects
-            .push(xy_rect::new(0., 555., 0., 555., 555., white.clone()));
+            .push(XyRect::new(0., 555., 0., 555., 555., white.clone()));
 
         let mut box1: Arc<dyn Hittable> = BoxRender::new(
             Vec3::new(0., 0., 0.),
@@ -308,15 +311,15 @@ impl ImageEnvBuilder {
             Vec3::new(278., 278., 0.),
             Vec3::new(0., 1., 0.),
             40.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
     }
 
-    pub fn cornell_smoke() -> (Camera, Arc<HittableList>) {
+    pub fn cornell_smoke(aspect_ratio: f64) -> Scene {
         let mut world = HittableList::new(vec![]);
 
         let red = Lamberian::new(SolidColor::new(Vec3::new(0.65, 0.05, 0.05)));
@@ -326,22 +329,20 @@ impl ImageEnvBuilder {
 
         world
             .objects
-            .push(yz_rect::new(0., 555., 0., 555., 555., green));
-        world
-            .objects
-            .push(yz_rect::new(0., 555., 0., 555., 0., red));
+            .push(YzRect::new(0., 555., 0., 555., 555., green));
+        world.objects.push(YzRect::new(0., 555., 0., 555., 0., red));
         world
             .objects
-            .push(xz_rect::new(113., 443., 127., 432., 554., light));
+            .push(XzRect::new(113., 443., 127., 432., 554., light));
         world
             .objects
-            .push(xz_rect::new(0., 555., 0., 555., 0., white.clone()));
+            .push(XzRect::new(0., 555., 0., 555., 0., white.clone()));
         world
             .objects
-            .push(xz_rect::new(0., 555., 0., 555., 555., white.clone()));
+            .push(XzRect::new(0., 555., 0., 555., 555., white.clone()));
         world
             .objects
-            .push(xy_rect::new(0., 555., 0., 555., 555., white.clone()));
+            .push(XyRect::new(0., 555., 0., 555., 555., white.clone()));
 
         let mut box1: Arc<dyn Hittable> = BoxRender::new(
             Vec3::new(0., 0., 0.),
@@ -376,15 +377,15 @@ impl ImageEnvBuilder {
             Vec3::new(278., 278., 0.),
             Vec3::new(0., 1., 0.),
             40.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
     }
 
-    pub fn final_scene(random: &mut Random<f64>) -> (Camera, Arc<HittableList>) {
+    pub fn final_scene(aspect_ratio: f64, random: &mut Random<f64>) -> Scene {
         let mut boxes1 = HittableList::new(vec![]);
         let ground = Lamberian::new(SolidColor::new(Vec3::new(0.48, 0.83, 0.53)));
 
@@ -415,7 +416,7 @@ impl ImageEnvBuilder {
         let light = DiffuseLight::new(SolidColor::new(Vec3::new(7., 7., 7.)));
         world
             .objects
-            .push(xz_rect::new(123., 423., 147., 412., 554., light));
+            .push(XzRect::new(123., 423., 147., 412., 554., light));
 
         let center1 = Vec3::new(400., 400., 200.);
         let center2 = center1 + Vec3::new(30., 0., 0.);
@@ -495,11 +496,11 @@ impl ImageEnvBuilder {
             Vec3::new(278., 278., 0.),
             Vec3::new(0., 1., 0.),
             40.0,
-            ASPECT_RATIO,
+            aspect_ratio,
             0.1,
             0.,
             1.,
         );
-        (camera, Arc::from(return_world))
+        Scene::new(camera, Arc::from(return_world))
     }
 }
@@ -0,0 +1,175 @@
+use std::{error::Error, fmt::Display, str::FromStr, sync::Arc};
+
+use crate::{
+    aabb::Aabb, camera::Camera, random::Random, ray::Ray, renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// Turns a camera ray into the color of its sample: the light arriving along it for
+/// the path tracer, or something about the first surface it hits for the debug
+/// integrators.
+pub trait Integrator: Send + Sync {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3;
+}
+
+pub mod ambient_occlusion;
+pub mod bvh_heatmap;
+pub mod depth;
+pub mod normals;
+pub mod path;
+pub mod uv;
+
+/// The built-in integrators, as chosen on the command line or in a scene file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IntegratorKind {
+    #[default]
+    Path,
+    /// Ambient occlusion within `distance`, a tenth of the scene's size if `None`.
+    AmbientOcclusion {
+        distance: Option<f64>,
+    },
+    Normals,
+    Uv,
+    /// Distance to the first hit, fading to black at `far`, the far side of the
+    /// scene if `None`.
+    Depth {
+        far: Option<f64>,
+    },
+    /// Bounding boxes tested per camera ray, red at `scale` (100 if `None`).
+    BvhHeatmap {
+        scale: Option<f64>,
+    },
+}
+
+impl IntegratorKind {
+    /// The integrator called `name`, with its optional numeric `parameter`.
+    pub fn new(name: &str, parameter: Option<f64>) -> Result<Self, Box<dyn Error>> {
+        if parameter.is_some_and(|parameter| parameter.is_nan() || parameter <= 0.) {
+            return Err(format!("the parameter of integrator '{}' must be positive", name).into());
+        }
+        let kind = match name {
+            "path" => IntegratorKind::Path,
+            "ao" => IntegratorKind::AmbientOcclusion {
+                distance: parameter,
+            },
+            "normals" => IntegratorKind::Normals,
+            "uv" => IntegratorKind::Uv,
+            "depth" => IntegratorKind::Depth { far: parameter },
+            "bvh" => IntegratorKind::BvhHeatmap { scale: parameter },
+            _ => return Err(format!("unknown integrator '{}'", name).into()),
+        };
+        if parameter.is_some() && kind.parameter().is_none() {
+            return Err(format!("integrator '{}' takes no parameter", name).into());
+        }
+        Ok(kind)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntegratorKind::Path => "path",
+            IntegratorKind::AmbientOcclusion { .. } => "ao",
+            IntegratorKind::Normals => "normals",
+            IntegratorKind::Uv => "uv",
+            IntegratorKind::Depth { .. } => "depth",
+            IntegratorKind::BvhHeatmap { .. } => "bvh",
+        }
+    }
+
+    pub fn parameter(self) -> Option<f64> {
+        match self {
+            IntegratorKind::AmbientOcclusion { distance } => distance,
+            IntegratorKind::Depth { far } => far,
+            IntegratorKind::BvhHeatmap { scale } => scale,
+            _ => None,
+        }
+    }
+
+    /// Builds the integrator for rendering `world` through `camera`. Parameters left
+    /// out are worked out from the world's bounding box, which is an error for worlds
+    /// that have none.
+    pub fn build(
+        self,
+        world: &dyn Hittable,
+        camera: &Camera,
+    ) -> Result<Arc<dyn Integrator>, Box<dyn Error>> {
+        let bounding_box = || {
+            let mut bbox = Aabb::default();
+            if world.bounding_box(camera.time0, camera.time1, &mut bbox) {
+                Ok(bbox)
+            } else {
+                Err(format!(
+                    "the scene has no bounding box to size integrator '{}' by; give it a \
+                     parameter, as in '{}:<number>'",
+                    self.name(),
+                    self.name()
+                ))
+            }
+        };
+        let integrator: Arc<dyn Integrator> = match self {
+            IntegratorKind::Path => Arc::new(path::PathTracer),
+            IntegratorKind::AmbientOcclusion { distance } => {
+                let distance = match distance {
+                    Some(distance) => distance,
+                    None => {
+                        let bbox = bounding_box()?;
+                        Vec3::length(bbox.max - bbox.min) / 10.
+                    }
+                };
+                Arc::new(ambient_occlusion::AmbientOcclusion { distance })
+            }
+            IntegratorKind::Normals => Arc::new(normals::Normals),
+            IntegratorKind::Uv => Arc::new(uv::Uv),
+            IntegratorKind::Depth { far } => {
+                let far = match far {
+                    Some(far) => far,
+                    None => {
+                        let bbox = bounding_box()?;
+                        let origin = camera.origin;
+                        let farthest = Vec3::new(
+                            (bbox.min.x_r - origin.x_r)
+                                .abs()
+                                .max((bbox.max.x_r - origin.x_r).abs()),
+                            (bbox.min.y_g - origin.y_g)
+                                .abs()
+                                .max((bbox.max.y_g - origin.y_g).abs()),
+                            (bbox.min.z_b - origin.z_b)
+                                .abs()
+                                .max((bbox.max.z_b - origin.z_b).abs()),
+                        );
+                        Vec3::length(farthest)
+                    }
+                };
+                Arc::new(depth::Depth { far })
+            }
+            IntegratorKind::BvhHeatmap { scale } => {
+                Arc::new(bvh_heatmap::BvhHeatmap::new(scale.unwrap_or(100.)))
+            }
+        };
+        Ok(integrator)
+    }
+}
+
+/// Parses `name` or `name:parameter`, e.g. `ao:50`.
+impl FromStr for IntegratorKind {
+    type Err = Box<dyn Error>;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.split_once(':') {
+            Some((name, parameter)) => {
+                let parameter = parameter
+                    .parse()
+                    .map_err(|_| format!("invalid integrator parameter '{}'", parameter))?;
+                Self::new(name, Some(parameter))
+            }
+            None => Self::new(text, None),
+        }
+    }
+}
+
+impl Display for IntegratorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.parameter() {
+            Some(parameter) => write!(f, "{}:{}", self.name(), parameter),
+            None => f.write_str(self.name()),
+        }
+    }
+}
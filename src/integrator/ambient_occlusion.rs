@@ -0,0 +1,33 @@
+use crate::{
+    integrator::Integrator, random::Random, ray::Ray, renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// White where nothing lies within `distance` above the first surface hit and darker
+/// the more of the hemisphere is blocked, weighted by the cosine like diffuse light.
+/// Rays that miss everything are black.
+pub struct AmbientOcclusion {
+    /// How far occluders count.
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        let Some(rec) = renderer.world.hit(ray, f64::MIN_POSITIVE, f64::MAX, rng) else {
+            return Vec3::default();
+        };
+        // The normal's tip plus a uniform unit vector is distributed by the cosine.
+        let mut direction = rec.normal + Vec3::random_unit_vector(rng);
+        if Vec3::near_zero(direction) {
+            direction = rec.normal;
+        }
+        let direction = Vec3::unit_vector(direction);
+        let occlusion_ray = rec.spawn_ray(direction, ray.time);
+        match renderer
+            .world
+            .hit(&occlusion_ray, f64::MIN_POSITIVE, self.distance, rng)
+        {
+            Some(_) => Vec3::default(),
+            None => Vec3::new(1., 1., 1.),
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{
+    bvh_node::{self, BoxTestCounting},
+    integrator::Integrator,
+    random::Random,
+    ray::Ray,
+    renderer::Renderer,
+    vec3::Vec3,
+    Hittable,
+};
+
+/// Cost of tracing the camera ray, as the number of bounding boxes of `BvhNode`s and
+/// mesh BVHs it was tested against: blue for none, green at half of `scale` and red
+/// at `scale` and above.
+pub struct BvhHeatmap {
+    scale: f64,
+    _counting: BoxTestCounting,
+}
+
+impl BvhHeatmap {
+    pub fn new(scale: f64) -> Self {
+        Self {
+            scale,
+            _counting: BoxTestCounting::new(),
+        }
+    }
+}
+
+impl Integrator for BvhHeatmap {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        let before = bvh_node::box_tests();
+        renderer.world.hit(ray, f64::MIN_POSITIVE, f64::MAX, rng);
+        let tests = bvh_node::box_tests() - before;
+        let t = (tests as f64 / self.scale).min(1.);
+        Vec3::new(
+            (2. * t - 1.).max(0.),
+            1. - (2. * t - 1.).abs(),
+            (1. - 2. * t).max(0.),
+        )
+    }
+}
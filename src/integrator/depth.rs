@@ -0,0 +1,21 @@
+use crate::{
+    integrator::Integrator, random::Random, ray::Ray, renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// Distance from the camera to the first surface hit, white right in front of the
+/// camera and fading to black at `far`. Rays that miss everything are black.
+pub struct Depth {
+    /// Distance shown as black.
+    pub far: f64,
+}
+
+impl Integrator for Depth {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        let Some(rec) = renderer.world.hit(ray, f64::MIN_POSITIVE, f64::MAX, rng) else {
+            return Vec3::default();
+        };
+        let distance = rec.t * Vec3::length(ray.direction);
+        let brightness = (1. - distance / self.far).clamp(0., 1.);
+        Vec3::new(brightness, brightness, brightness)
+    }
+}
@@ -0,0 +1,24 @@
+use crate::{
+    integrator::Integrator, random::Random, ray::Ray, renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// Outward normal of the first surface hit, mapped from [-1, 1] to [0, 1] per axis,
+/// so faces pointing along +x are red, +y green and +z blue. Rays that miss everything
+/// are black.
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        match renderer.world.hit(ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) => {
+                let normal = if rec.front_face {
+                    rec.normal
+                } else {
+                    -rec.normal
+                };
+                (normal + Vec3::new(1., 1., 1.)) * 0.5
+            }
+            None => Vec3::default(),
+        }
+    }
+}
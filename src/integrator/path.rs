@@ -0,0 +1,206 @@
+use crate::{
+    hittable::HitRecord, integrator::Integrator, material::Material, random::Random, ray::Ray,
+    renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// Path tracer: follows a path from the camera ray and returns the radiance arriving
+/// along it. Every bounce samples the material and weights the path by
+/// `f * cosine / pdf`.
+///
+/// At every non-specular bounce a direction towards one of the renderer's `lights`
+/// is sampled too and the emission found along it added (next-event estimation).
+/// Emission, or the background, that the next bounce finds could then have been
+/// sampled either way, so both estimates are weighted with the `settings.mis`
+/// heuristic.
+///
+/// After `settings.roulette_depth` bounces, paths are continued with a probability
+/// that falls with their throughput and the survivors weighted up to make up for the
+/// ones ended (Russian roulette), so dark paths stop early without darkening the
+/// image.
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        let settings = &renderer.settings;
+        let hittable_list = &renderer.world;
+        let lights = &renderer.lights;
+        let background = &renderer.background;
+        let mut ray = *ray;
+        let mut radiance = Vec3::default();
+        let mut throughput = Vec3::new(1., 1., 1.);
+        // Where the current ray starts and the BSDF density it was drawn with, if the
+        // lights were sampled there as well.
+        let mut lights_sampled_from: Option<(Vec3, f64)> = None;
+        // Bounces followed so far, per `Lobe`.
+        let mut bounces = [0u8; 4];
+        for depth in 1..=settings.max_depth {
+            let hit = hittable_list.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng);
+            let emitted = match &hit {
+                Some(rec) => rec.material.as_ref().emitted(rec.u, rec.v, rec.p),
+                None => background.value(ray.direction),
+            };
+            if emitted != Vec3::default() {
+                let weight = match lights_sampled_from {
+                    Some((origin, bsdf_pdf)) => settings
+                        .mis
+                        .weight(bsdf_pdf, lights.pdf_value(origin, ray.direction, rng)),
+                    None => 1.,
+                };
+                radiance += throughput * emitted * weight;
+            }
+            let Some(rec) = hit else {
+                break;
+            };
+            let material = rec.material.as_ref();
+            let lights_sampled = !material.is_delta() && !lights.objects.is_empty();
+            if lights_sampled {
+                radiance += throughput * sample_lights(&ray, &rec, renderer, rng);
+            }
+            if depth == settings.max_depth {
+                break;
+            }
+            let sample = match material.sample(rng, &ray, &rec) {
+                Some(sample) if sample.pdf > 0. => sample,
+                _ => break,
+            };
+            let count = &mut bounces[sample.lobe as usize];
+            *count += 1;
+            if settings
+                .bounces
+                .limit(sample.lobe)
+                .is_some_and(|limit| *count > limit)
+            {
+                break;
+            }
+            lights_sampled_from = (lights_sampled && !sample.delta).then_some((rec.p, sample.pdf));
+            throughput =
+                throughput * sample.f * material.cosine(&rec, sample.direction) / sample.pdf;
+            if depth > settings.roulette_depth {
+                let survival = throughput
+                    .x_r
+                    .max(throughput.y_g)
+                    .max(throughput.z_b)
+                    .min(1.);
+                if survival <= rng.random(None, None) {
+                    break;
+                }
+                throughput /= survival;
+            }
+            ray = rec.spawn_ray(sample.direction, ray.time);
+        }
+        radiance
+    }
+}
+
+/// Light reflected at `rec` towards the previous vertex from a direction sampled
+/// towards `lights`, through a shadow ray, weighted against finding the same light
+/// by sampling the BSDF. Shadow rays that leave the scene see the background.
+fn sample_lights(
+    ray_in: &Ray,
+    rec: &HitRecord,
+    renderer: &Renderer,
+    rng: &mut Random<f64>,
+) -> Vec3 {
+    let lights = &renderer.lights;
+    let material = rec.material.as_ref();
+    let direction = lights.random(rec.p, rng);
+    let f = material.eval(ray_in, rec, direction);
+    if f == Vec3::default() {
+        return Vec3::default();
+    }
+    let pdf = lights.pdf_value(rec.p, direction, rng);
+    if pdf <= 0. {
+        return Vec3::default();
+    }
+    let shadow_ray = rec.spawn_ray(direction, ray_in.time);
+    let emitted = match renderer
+        .world
+        .hit(&shadow_ray, f64::MIN_POSITIVE, f64::MAX, rng)
+    {
+        Some(light_rec) => {
+            light_rec
+                .material
+                .as_ref()
+                .emitted(light_rec.u, light_rec.v, light_rec.p)
+        }
+        None => renderer.background.value(direction),
+    };
+    let weight = renderer
+        .settings
+        .mis
+        .weight(pdf, material.pdf(ray_in, rec, direction));
+    f * material.cosine(rec, direction) * emitted * weight / pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        random::SampleRng,
+        renderer::{BounceLimits, RenderSettings},
+        scene_file::SceneFile,
+    };
+
+    fn render(source: &str, settings: RenderSettings) -> Vec<Vec3> {
+        let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        let scene =
+            SceneFile::parse(source, Path::new("."), settings.aspect_ratio(), &mut random).unwrap();
+        Renderer::new(scene, settings)
+            .unwrap()
+            .render()
+            .unwrap()
+            .pixels
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings::new()
+            .width(32)
+            .height(24)
+            .samples_per_pixel(16)
+            .seed(3)
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        // A sphere resting on a floor under a white sky, which light bounces between.
+        let source = "camera { lookfrom 0 1 4 lookat 0 0.5 0 vfov 50 aperture 0 }
+             background solid 1 1 1
+             xz_rect -50 50 -50 50 0 (lambertian (solid 0.7 0.7 0.7))
+             sphere 0 1 0 1 (lambertian (solid 0.7 0.7 0.7))";
+        let mean = |settings: RenderSettings| {
+            let pixels = render(source, settings.max_depth(30));
+            pixels.iter().map(|pixel| pixel.y_g).sum::<f64>() / pixels.len() as f64
+        };
+        let without = mean(settings().roulette_depth(u8::MAX).samples_per_pixel(64));
+        let with = mean(settings().roulette_depth(0).samples_per_pixel(64));
+        assert!(
+            (with - without).abs() < 0.01 * without,
+            "{} != {}",
+            with,
+            without
+        );
+    }
+
+    #[test]
+    fn transmission_limit_stops_only_paths_through_glass() {
+        // A glass ball in front of a white wall, seen straight through its centre.
+        let source = "camera { lookfrom 0 0 5 lookat 0 0 0 vfov 10 aperture 0 }
+             background solid 0 0 0
+             xy_rect -50 50 -50 50 -3 (diffuse_light (solid 1 1 1))
+             sphere 0 0 0 1 (dielectric 1.5)";
+        let mean = |bounces: BounceLimits| {
+            let pixels = render(source, settings().max_depth(16).bounces(bounces));
+            pixels.iter().map(|pixel| pixel.y_g).sum::<f64>() / pixels.len() as f64
+        };
+        let unlimited = mean(BounceLimits::new());
+        // Into the ball and out again takes two transmissions.
+        assert!(mean(BounceLimits::new().transmission(2)) > 0.9 * unlimited);
+        assert!(mean(BounceLimits::new().diffuse(0).glossy(0)) > 0.9 * unlimited);
+        // Only what the ball reflects is left.
+        assert!(mean(BounceLimits::new().transmission(1)) < 0.15 * unlimited);
+    }
+}
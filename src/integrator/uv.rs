@@ -0,0 +1,16 @@
+use crate::{
+    integrator::Integrator, random::Random, ray::Ray, renderer::Renderer, vec3::Vec3, Hittable,
+};
+
+/// Texture coordinates of the first surface hit, `u` in red and `v` in green. Rays
+/// that miss everything are black.
+pub struct Uv;
+
+impl Integrator for Uv {
+    fn ray_color(&self, ray: &Ray, renderer: &Renderer, rng: &mut Random<f64>) -> Vec3 {
+        match renderer.world.hit(ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) => Vec3::new(rec.u, rec.v, 0.),
+            None => Vec3::default(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+pub mod aabb;
+pub mod aov;
+pub mod background;
+pub mod bvh_node;
+pub mod camera;
+pub mod checkpoint;
+pub mod denoiser;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod image_env_builder;
+pub mod integrator;
+pub mod loaders;
+pub mod material;
+pub mod objects;
+pub mod onb;
+pub mod output;
+pub mod perlin;
+pub mod random;
+pub mod ray;
+pub mod renderer;
+pub mod scene;
+pub mod scene_file;
+pub mod texture;
+pub mod tone_map;
+pub mod vec3;
+
+pub use camera::Camera;
+pub use hittable::HittableList;
+pub use material::Material;
+pub use objects::Hittable;
+pub use ray::Ray;
+pub use renderer::{Framebuffer, RenderSettings, Renderer};
+pub use scene::Scene;
+pub use vec3::Vec3;
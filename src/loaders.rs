@@ -0,0 +1,5 @@
+//! Importers for meshes and scenes exported by other tools.
+
+pub mod gltf;
+pub mod obj;
+pub mod ply;
@@ -0,0 +1,495 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ::gltf::{buffer, camera::Projection, image, mesh::Mode, Gltf, Node};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    aabb::Aabb,
+    bvh_node::BvhNode,
+    camera::Camera,
+    hittable::HittableList,
+    material::{
+        dielectric::Dielectric, diffuse_light::DiffuseLight, lamberian::Lamberian, metal::Metal,
+        Material,
+    },
+    objects::triangle_mesh::TriangleMesh,
+    scene::Scene,
+    texture::{image_texture::ImageTexture, solid_color::SolidColor, Texture},
+    vec3::Vec3,
+    Hittable,
+};
+
+/// Column-major 4x4 matrix, the layout glTF stores node transforms in.
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Geometry and (if the file has one) the camera of an imported glTF scene.
+pub struct GltfScene {
+    pub camera: Option<Camera>,
+    pub world: Arc<BvhNode>,
+}
+
+pub struct GltfLoader {}
+
+impl GltfLoader {
+    /// Loads the default scene (or the first one) of a `.gltf` or `.glb` file.
+    ///
+    /// Node transforms are baked into the vertices, so every primitive becomes a
+    /// world-space `TriangleMesh`. The first perspective camera found is returned
+    /// with `aspect_ratio` in place of its own. Primitives without a material use
+    /// `default_material`.
+    pub fn load(
+        path: &Path,
+        default_material: Arc<dyn Material>,
+        aspect_ratio: f64,
+    ) -> Result<GltfScene, Box<dyn Error>> {
+        Self::import(path, default_material, aspect_ratio)
+            .map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    /// Loads a glTF file as a complete scene. Without a camera in the file, the
+    /// camera looks down -z at the whole model.
+    pub fn load_scene(path: &Path, aspect_ratio: f64) -> Result<Scene, Box<dyn Error>> {
+        let default_material = Lamberian::new(SolidColor::new(Vec3::new(0.8, 0.8, 0.8)));
+        let scene = Self::load(path, default_material, aspect_ratio)?;
+        let camera = match scene.camera {
+            Some(camera) => camera,
+            None => {
+                let mut bbox = Aabb::default();
+                scene.world.bounding_box(0., 1., &mut bbox);
+                Self::framing_camera(bbox, aspect_ratio)
+            }
+        };
+        Ok(Scene::new(
+            camera,
+            Arc::new(HittableList::new(vec![scene.world])),
+        ))
+    }
+
+    fn framing_camera(bbox: Aabb, aspect_ratio: f64) -> Camera {
+        let vfov: f64 = 40.;
+        let center = (bbox.min + bbox.max) / 2.;
+        let radius = Vec3::length(bbox.max - bbox.min) / 2.;
+        let distance = radius / (vfov / 2.).to_radians().sin();
+        Camera::new(
+            center + Vec3::new(0., 0., distance),
+            center,
+            Vec3::new(0., 1., 0.),
+            vfov,
+            aspect_ratio,
+            0.,
+            0.,
+            1.,
+        )
+    }
+
+    fn import(
+        path: &Path,
+        default_material: Arc<dyn Material>,
+        aspect_ratio: f64,
+    ) -> Result<GltfScene, Box<dyn Error>> {
+        let Gltf { document, blob } = Gltf::open(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut blob = blob;
+        let mut buffers = Vec::with_capacity(document.buffers().len());
+        for buffer in document.buffers() {
+            let data = match buffer.source() {
+                buffer::Source::Bin => blob.take().ok_or("missing binary chunk")?,
+                buffer::Source::Uri(uri) => Self::read_uri(base_dir, uri)?,
+            };
+            if data.len() < buffer.length() {
+                return Err(format!("buffer {} is too short", buffer.index()).into());
+            }
+            buffers.push(data);
+        }
+
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or("file has no scene")?;
+        let mut importer = Importer {
+            buffers: &buffers,
+            base_dir: base_dir.to_path_buf(),
+            aspect_ratio,
+            default_material,
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+            meshes: vec![],
+            camera: None,
+        };
+        for node in scene.nodes() {
+            importer.visit(&node, &IDENTITY)?;
+        }
+        if importer.meshes.is_empty() {
+            return Err("scene has no triangles".into());
+        }
+        Ok(GltfScene {
+            camera: importer.camera,
+            world: Arc::new(BvhNode::new(&mut importer.meshes, 0., 1.)),
+        })
+    }
+
+    /// Reads a buffer or image URI: either an embedded base64 `data:` URI or a
+    /// file relative to the glTF file.
+    fn read_uri(base_dir: &Path, uri: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (header, payload) = data.split_once(',').ok_or("malformed data URI")?;
+            if !header.ends_with(";base64") {
+                return Err("only base64 data URIs are supported".into());
+            }
+            return Ok(STANDARD.decode(payload)?);
+        }
+        let file = base_dir.join(Self::percent_decode(uri));
+        fs::read(&file).map_err(|err| format!("cannot read '{}': {}", file.display(), err).into())
+    }
+
+    fn percent_decode(uri: &str) -> String {
+        let bytes = uri.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let escaped = bytes
+                .get(i + 1..i + 3)
+                .filter(|_| bytes[i] == b'%')
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+            match escaped {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                None => {
+                    decoded.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+}
+
+/// State shared while walking the node hierarchy of one scene.
+struct Importer<'a> {
+    buffers: &'a [Vec<u8>],
+    base_dir: PathBuf,
+    aspect_ratio: f64,
+    default_material: Arc<dyn Material>,
+    materials: HashMap<usize, Arc<dyn Material>>,
+    textures: HashMap<usize, Arc<dyn Texture>>,
+    meshes: Vec<Arc<dyn Hittable>>,
+    camera: Option<Camera>,
+}
+
+impl<'a> Importer<'a> {
+    fn visit(&mut self, node: &Node, parent: &Matrix) -> Result<(), Box<dyn Error>> {
+        let local = node
+            .transform()
+            .matrix()
+            .map(|column| column.map(f64::from));
+        let world = Self::multiply(parent, &local);
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, &world)
+                    .map_err(|err| format!("mesh {}: {}", mesh.index(), err))?;
+            }
+        }
+        if let (None, Some(camera)) = (&self.camera, node.camera()) {
+            if let Projection::Perspective(perspective) = camera.projection() {
+                let origin = Self::transform_point(&world, Vec3::default());
+                let forward = Self::transform_vector(&world, Vec3::new(0., 0., -1.));
+                let up = Self::transform_vector(&world, Vec3::new(0., 1., 0.));
+                self.camera = Some(Camera::new(
+                    origin,
+                    origin + Vec3::unit_vector(forward),
+                    up,
+                    f64::from(perspective.yfov()).to_degrees(),
+                    self.aspect_ratio,
+                    0.,
+                    0.,
+                    1.,
+                ));
+            }
+        }
+        for child in node.children() {
+            self.visit(&child, &world)?;
+        }
+        Ok(())
+    }
+
+    fn add_primitive(
+        &mut self,
+        primitive: &::gltf::Primitive,
+        world: &Matrix,
+    ) -> Result<(), Box<dyn Error>> {
+        let buffers = self.buffers;
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions
+                .map(|p| Self::transform_point(world, Self::vec3(p)))
+                .collect(),
+            None => return Ok(()),
+        };
+        let normals = reader.read_normals().map(|normals| {
+            normals
+                .map(|n| Self::transform_normal(world, Self::vec3(n)))
+                .collect()
+        });
+        let material = primitive.material();
+        let tex_coord = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map_or(0, |info| info.tex_coord());
+        // glTF puts the texture origin at the top left, `ImageTexture` at the bottom left.
+        let uvs = reader.read_tex_coords(tex_coord).map(|uvs| {
+            uvs.into_f32()
+                .map(|[u, v]| (f64::from(u), 1. - f64::from(v)))
+                .collect()
+        });
+        let vertices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if vertices.iter().any(|&v| v as usize >= positions.len()) {
+            return Err("vertex index out of range".into());
+        }
+        let indices: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::Triangles => vertices
+                .chunks_exact(3)
+                .map(|face| [face[0], face[1], face[2]])
+                .collect(),
+            // Every other strip triangle is swapped to keep the winding consistent.
+            Mode::TriangleStrip => (0..vertices.len().saturating_sub(2))
+                .map(|i| match i % 2 {
+                    0 => [vertices[i], vertices[i + 1], vertices[i + 2]],
+                    _ => [vertices[i + 1], vertices[i], vertices[i + 2]],
+                })
+                .collect(),
+            Mode::TriangleFan => (1..vertices.len().saturating_sub(1))
+                .map(|i| [vertices[0], vertices[i], vertices[i + 1]])
+                .collect(),
+            // Points and lines have no area to hit.
+            _ => return Ok(()),
+        };
+        if indices.is_empty() {
+            return Ok(());
+        }
+        let material = self.material(&material)?;
+        self.meshes.push(TriangleMesh::new(
+            positions, normals, uvs, None, indices, material,
+        ));
+        Ok(())
+    }
+
+    /// Maps a metallic-roughness material onto the closest of ours: emission wins,
+    /// then transmission, then metals (roughness as fuzz), and the rest is diffuse.
+    fn material(
+        &mut self,
+        material: &::gltf::Material,
+    ) -> Result<Arc<dyn Material>, Box<dyn Error>> {
+        let index = match material.index() {
+            Some(index) => index,
+            None => return Ok(self.default_material.clone()),
+        };
+        if let Some(material) = self.materials.get(&index) {
+            return Ok(material.clone());
+        }
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor().map(f64::from);
+        let base_color = Vec3::new(r, g, b);
+        let [r, g, b] = material.emissive_factor().map(f64::from);
+        let emissive = Vec3::new(r, g, b) * f64::from(material.emissive_strength().unwrap_or(1.));
+        let transmission = material
+            .transmission()
+            .map_or(0., |transmission| transmission.transmission_factor());
+
+        let converted: Arc<dyn Material> = if emissive.x_r.max(emissive.y_g).max(emissive.z_b) > 0.
+        {
+            let emit = match material.emissive_texture() {
+                Some(info) => self.texture(&info.texture())?,
+                None => SolidColor::new(emissive),
+            };
+            DiffuseLight::new(emit)
+        } else if transmission > 0. {
+            Dielectric::new(f64::from(material.ior().unwrap_or(1.5)))
+        } else if pbr.metallic_factor() >= 0.5 {
+            Metal::new(base_color, f64::from(pbr.roughness_factor()))
+        } else {
+            let albedo = match pbr.base_color_texture() {
+                Some(info) => self.texture(&info.texture())?,
+                None => SolidColor::new(base_color),
+            };
+            Lamberian::new(albedo)
+        };
+        self.materials.insert(index, converted.clone());
+        Ok(converted)
+    }
+
+    fn texture(&mut self, texture: &::gltf::Texture) -> Result<Arc<dyn Texture>, Box<dyn Error>> {
+        let image = texture.source();
+        if let Some(texture) = self.textures.get(&image.index()) {
+            return Ok(texture.clone());
+        }
+        let loaded: Arc<dyn Texture> = match image.source() {
+            image::Source::View { view, .. } => {
+                let bytes = self.buffers[view.buffer().index()]
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or("image buffer view out of range")?;
+                ImageTexture::from_memory(bytes)?
+            }
+            image::Source::Uri { uri, .. } => {
+                ImageTexture::from_memory(&GltfLoader::read_uri(&self.base_dir, uri)?)?
+            }
+        };
+        self.textures.insert(image.index(), loaded.clone());
+        Ok(loaded)
+    }
+
+    fn vec3(v: [f32; 3]) -> Vec3 {
+        Vec3::new(f64::from(v[0]), f64::from(v[1]), f64::from(v[2]))
+    }
+
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut m = [[0.; 4]; 4];
+        for (column, b_column) in b.iter().enumerate() {
+            for row in 0..4 {
+                m[column][row] = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+            }
+        }
+        m
+    }
+
+    fn transform_vector(m: &Matrix, v: Vec3) -> Vec3 {
+        Vec3::new(
+            m[0][0] * v.x_r + m[1][0] * v.y_g + m[2][0] * v.z_b,
+            m[0][1] * v.x_r + m[1][1] * v.y_g + m[2][1] * v.z_b,
+            m[0][2] * v.x_r + m[1][2] * v.y_g + m[2][2] * v.z_b,
+        )
+    }
+
+    fn transform_point(m: &Matrix, p: Vec3) -> Vec3 {
+        Self::transform_vector(m, p) + Vec3::new(m[3][0], m[3][1], m[3][2])
+    }
+
+    /// Normals go through the cofactor matrix (the inverse transpose scaled by the
+    /// determinant), which keeps them perpendicular under non-uniform scaling.
+    fn transform_normal(m: &Matrix, n: Vec3) -> Vec3 {
+        let column = |i: usize| Vec3::new(m[i][0], m[i][1], m[i][2]);
+        let (a0, a1, a2) = (column(0), column(1), column(2));
+        Vec3::cross(a1, a2) * n.x_r + Vec3::cross(a2, a0) * n.y_g + Vec3::cross(a0, a1) * n.z_b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ::image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        random::{Random, SampleRng},
+        ray::Ray,
+    };
+
+    /// A triangle in a scaled node under a rotated and moved parent, textured from an
+    /// embedded PNG, and a camera turned to look down -x.
+    fn gltf_source() -> String {
+        let mut buffer = vec![];
+        for value in [0f32, 0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1.] {
+            buffer.extend(value.to_le_bytes());
+        }
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([64, 128, 255])))
+            .write_to(&mut png, ImageOutputFormat::Png)
+            .unwrap();
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        format!(
+            r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0, 2]}}],
+  "nodes": [
+    {{"translation": [10, 0, 0], "rotation": [0, 0, {half}, {half}], "children": [1]}},
+    {{"scale": [2, 2, 2], "mesh": 0}},
+    {{"translation": [0, 1, 5], "rotation": [0, {half}, 0, {half}], "camera": 0}}
+  ],
+  "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.8, "znear": 0.1}}}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "TEXCOORD_0": 1}}, "material": 0}}]}}],
+  "materials": [{{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}, "metallicFactor": 0}}}}],
+  "textures": [{{"source": 0}}],
+  "images": [{{"uri": "data:image/png;base64,{png}"}}],
+  "buffers": [{{"byteLength": 60, "uri": "data:application/octet-stream;base64,{buffer}"}}],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+    {{"buffer": 0, "byteOffset": 36, "byteLength": 24}}
+  ],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+    {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}}
+  ]
+}}"#,
+            half = half,
+            png = STANDARD.encode(png.into_inner()),
+            buffer = STANDARD.encode(buffer),
+        )
+    }
+
+    #[test]
+    fn nodes_cameras_and_embedded_data_are_imported() {
+        let path =
+            std::env::temp_dir().join(format!("raytracing_gltf_test_{}.gltf", std::process::id()));
+        fs::write(&path, gltf_source()).unwrap();
+        let default_material = Lamberian::new(SolidColor::new(Vec3::new(0.8, 0.8, 0.8)));
+        let scene = GltfLoader::load(&path, default_material, 2.);
+        fs::remove_file(&path).unwrap();
+        let scene = scene.unwrap();
+
+        // Scaled by 2, turned by 90 degrees around z and moved along x, the corners
+        // (0, 0, 0), (1, 0, 0) and (0, 1, 0) end up at (10, 0, 0), (10, 2, 0) and
+        // (8, 0, 0).
+        let mut rng = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        let hit = |x: f64, y: f64, rng: &mut Random<f64>| {
+            let ray = Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.), 0.);
+            scene.world.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng)
+        };
+        let rec = hit(9.99, 1.98, &mut rng).unwrap();
+        assert!(Vec3::length(rec.p - Vec3::new(9.99, 1.98, 0.)) < 1e-9);
+        assert!(hit(10.01, 1., &mut rng).is_none());
+        assert!(hit(9., 1.01, &mut rng).is_none());
+        let mut bbox = Aabb::default();
+        assert!(scene.world.bounding_box(0., 1., &mut bbox));
+        assert!(Vec3::length(bbox.min - Vec3::new(8., 0., 0.)) < 1e-3);
+        assert!(Vec3::length(bbox.max - Vec3::new(10., 2., 0.)) < 1e-3);
+
+        // The embedded texture is decoded from sRGB.
+        let albedo = rec.material.as_ref().unwrap().albedo(&rec);
+        let texel = Vec3::new(
+            Vec3::srgb_eotf(64. / 255.),
+            Vec3::srgb_eotf(128. / 255.),
+            1.,
+        );
+        assert!(Vec3::length(albedo - texel) < 1e-6);
+
+        // The camera looks down its node's -z, turned to -x, with the file's field of
+        // view and the aspect ratio asked for.
+        let camera = scene.camera.unwrap();
+        assert!(Vec3::length(camera.origin - Vec3::new(0., 1., 5.)) < 1e-9);
+        assert!(Vec3::length(camera.w - Vec3::new(1., 0., 0.)) < 1e-6);
+        assert!(Vec3::length(camera.v - Vec3::new(0., 1., 0.)) < 1e-6);
+        let height = 2. * 0.4f64.tan();
+        assert!((Vec3::length(camera.vertical) - height).abs() < 1e-6);
+        assert!((Vec3::length(camera.horizontal) - 2. * height).abs() < 1e-6);
+    }
+}
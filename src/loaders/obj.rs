@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    bvh_node::BvhNode,
+    material::{
+        dielectric::Dielectric, diffuse_light::DiffuseLight, lamberian::Lamberian, metal::Metal,
+        Material,
+    },
+    objects::triangle_mesh::TriangleMesh,
+    texture::{image_texture::ImageTexture, solid_color::SolidColor, Texture},
+    vec3::Vec3,
+    Hittable,
+};
+
+/// The subset of an MTL material description that maps onto our materials.
+struct MtlMaterial {
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    ns: f64,
+    ni: f64,
+    dissolve: f64,
+    illum: u32,
+    map_kd: Option<PathBuf>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            kd: Vec3::new(0.8, 0.8, 0.8),
+            ks: Vec3::default(),
+            ke: Vec3::default(),
+            ns: 0.,
+            ni: 1.5,
+            dissolve: 1.,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+}
+
+/// Faces sharing a group name and a material; each one becomes a `TriangleMesh`.
+#[derive(Default)]
+struct ObjGroup {
+    material: Option<String>,
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    has_normals: bool,
+    has_uvs: bool,
+    indices: Vec<[u32; 3]>,
+    vertex_ids: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+}
+
+pub struct ObjLoader {}
+
+impl ObjLoader {
+    /// Loads a Wavefront OBJ file together with its MTL libraries.
+    ///
+    /// Every group (`g`/`o`) and material (`usemtl`) combination becomes its own
+    /// `TriangleMesh`; all of them are put into one `BvhNode`. Faces without a
+    /// material use `default_material`.
+    pub fn load(
+        path: &Path,
+        default_material: Arc<dyn Material>,
+    ) -> Result<Arc<BvhNode>, Box<dyn Error>> {
+        let source = fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let error = |line: usize, message: String| -> Box<dyn Error> {
+            format!("{}:{}: {}", path.display(), line, message).into()
+        };
+
+        let mut positions: Vec<Vec3> = vec![];
+        let mut normals: Vec<Vec3> = vec![];
+        let mut uvs: Vec<(f64, f64)> = vec![];
+        let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+        let mut groups: Vec<ObjGroup> = vec![];
+        let mut group_ids: HashMap<(String, Option<String>), usize> = HashMap::new();
+        let mut group_name = String::new();
+        let mut material_name: Option<String> = None;
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut parts = line.split_whitespace();
+            let keyword = match parts.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let args: Vec<&str> = parts.collect();
+            match keyword {
+                "v" => {
+                    positions.push(Self::parse_vec3(&args).map_err(|err| error(line_number, err))?)
+                }
+                "vn" => {
+                    normals.push(Self::parse_vec3(&args).map_err(|err| error(line_number, err))?)
+                }
+                "vt" => {
+                    let u = Self::parse_number(args.first().copied())
+                        .map_err(|err| error(line_number, err))?;
+                    let v = Self::parse_number(Some(args.get(1).copied().unwrap_or("0")))
+                        .map_err(|err| error(line_number, err))?;
+                    uvs.push((u, v));
+                }
+                "g" | "o" => group_name = args.join(" "),
+                "usemtl" => material_name = Some(args.join(" ")),
+                "mtllib" => {
+                    for library in &args {
+                        let library_path = base_dir.join(library);
+                        Self::load_mtl(&library_path, &mut mtl_materials)?;
+                    }
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(error(line_number, "face needs at least 3 vertices".into()));
+                    }
+                    let key = (group_name.clone(), material_name.clone());
+                    let group_id = *group_ids.entry(key).or_insert_with(|| {
+                        groups.push(ObjGroup {
+                            material: material_name.clone(),
+                            has_normals: true,
+                            has_uvs: true,
+                            ..Default::default()
+                        });
+                        groups.len() - 1
+                    });
+                    let group = &mut groups[group_id];
+                    let mut corners = Vec::with_capacity(args.len());
+                    for corner in &args {
+                        let (v, vt, vn) =
+                            Self::parse_corner(corner, positions.len(), uvs.len(), normals.len())
+                                .map_err(|err| error(line_number, err))?;
+                        corners.push(Self::group_vertex(
+                            group, v, vt, vn, &positions, &uvs, &normals,
+                        ));
+                    }
+                    // Polygons are split into a triangle fan.
+                    for i in 1..corners.len() - 1 {
+                        group.indices.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut material_cache: HashMap<String, Arc<dyn Material>> = HashMap::new();
+        let mut meshes: Vec<Arc<dyn Hittable>> = vec![];
+        for group in groups {
+            let material = match &group.material {
+                Some(name) => match material_cache.get(name) {
+                    Some(material) => material.clone(),
+                    None => {
+                        let material = match mtl_materials.get(name) {
+                            Some(mtl) => Self::convert_material(mtl)?,
+                            None => default_material.clone(),
+                        };
+                        material_cache.insert(name.clone(), material.clone());
+                        material
+                    }
+                },
+                None => default_material.clone(),
+            };
+            meshes.push(TriangleMesh::new(
+                group.positions,
+                group.has_normals.then_some(group.normals),
+                group.has_uvs.then_some(group.uvs),
+                None,
+                group.indices,
+                material,
+            ));
+        }
+        if meshes.is_empty() {
+            return Err(format!("{}: no faces", path.display()).into());
+        }
+        Ok(Arc::new(BvhNode::new(&mut meshes, 0., 1.)))
+    }
+
+    /// Returns the group-local index of a face corner, adding the vertex on first use.
+    fn group_vertex(
+        group: &mut ObjGroup,
+        v: usize,
+        vt: Option<usize>,
+        vn: Option<usize>,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> u32 {
+        if let Some(&index) = group.vertex_ids.get(&(v, vt, vn)) {
+            return index;
+        }
+        let index = group.positions.len() as u32;
+        group.positions.push(positions[v]);
+        group.uvs.push(vt.map(|vt| uvs[vt]).unwrap_or_default());
+        group
+            .normals
+            .push(vn.map(|vn| normals[vn]).unwrap_or_default());
+        group.has_uvs &= vt.is_some();
+        group.has_normals &= vn.is_some();
+        group.vertex_ids.insert((v, vt, vn), index);
+        index
+    }
+
+    /// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, resolving 1-based and negative indices.
+    fn parse_corner(
+        corner: &str,
+        position_count: usize,
+        uv_count: usize,
+        normal_count: usize,
+    ) -> Result<(usize, Option<usize>, Option<usize>), String> {
+        let resolve = |text: &str, count: usize| -> Result<usize, String> {
+            let index: i64 = text
+                .parse()
+                .map_err(|_| format!("invalid index '{}'", text))?;
+            let resolved = if index < 0 {
+                count as i64 + index
+            } else {
+                index - 1
+            };
+            if resolved < 0 || resolved >= count as i64 {
+                return Err(format!("index {} out of range", index));
+            }
+            Ok(resolved as usize)
+        };
+        let mut fields = corner.split('/');
+        let v = resolve(fields.next().unwrap_or(""), position_count)?;
+        let vt = match fields.next() {
+            Some(text) if !text.is_empty() => Some(resolve(text, uv_count)?),
+            _ => None,
+        };
+        let vn = match fields.next() {
+            Some(text) if !text.is_empty() => Some(resolve(text, normal_count)?),
+            _ => None,
+        };
+        Ok((v, vt, vn))
+    }
+
+    fn parse_number(text: Option<&str>) -> Result<f64, String> {
+        let text = text.ok_or_else(|| "missing number".to_string())?;
+        text.parse()
+            .map_err(|_| format!("invalid number '{}'", text))
+    }
+
+    fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+        Ok(Vec3::new(
+            Self::parse_number(args.first().copied())?,
+            Self::parse_number(args.get(1).copied())?,
+            Self::parse_number(args.get(2).copied())?,
+        ))
+    }
+
+    fn load_mtl(
+        path: &Path,
+        materials: &mut HashMap<String, MtlMaterial>,
+    ) -> Result<(), Box<dyn Error>> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| format!("cannot read '{}': {}", path.display(), err))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let error = |line: usize, message: String| -> Box<dyn Error> {
+            format!("{}:{}: {}", path.display(), line, message).into()
+        };
+        let mut current: Option<String> = None;
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut parts = line.split_whitespace();
+            let keyword = match parts.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let args: Vec<&str> = parts.collect();
+            if keyword == "newmtl" {
+                let name = args.join(" ");
+                materials.insert(name.clone(), MtlMaterial::default());
+                current = Some(name);
+                continue;
+            }
+            let material = match current.as_ref().and_then(|name| materials.get_mut(name)) {
+                Some(material) => material,
+                None => continue,
+            };
+            let number = |index: usize| {
+                Self::parse_number(args.get(index).copied()).map_err(|err| error(line_number, err))
+            };
+            match keyword {
+                "Kd" => material.kd = Self::parse_vec3(&args).map_err(|e| error(line_number, e))?,
+                "Ks" => material.ks = Self::parse_vec3(&args).map_err(|e| error(line_number, e))?,
+                "Ke" => material.ke = Self::parse_vec3(&args).map_err(|e| error(line_number, e))?,
+                "Ns" => material.ns = number(0)?,
+                "Ni" => material.ni = number(0)?,
+                "d" => material.dissolve = number(0)?,
+                "Tr" => material.dissolve = 1. - number(0)?,
+                "illum" => material.illum = number(0)? as u32,
+                // Options such as `-s 1 1 1` may precede the file name, which comes last.
+                "map_Kd" => material.map_kd = args.last().map(|file| base_dir.join(file)),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks the closest of our materials: emission wins, then transparency,
+    /// then mirror-like specular, and everything else is diffuse.
+    fn convert_material(mtl: &MtlMaterial) -> Result<Arc<dyn Material>, Box<dyn Error>> {
+        let max = |v: Vec3| v.x_r.max(v.y_g).max(v.z_b);
+        if max(mtl.ke) > 0. {
+            return Ok(DiffuseLight::new(SolidColor::new(mtl.ke)));
+        }
+        if mtl.dissolve < 1. || matches!(mtl.illum, 4 | 6 | 7 | 9) {
+            return Ok(Dielectric::new(if mtl.ni > 0. { mtl.ni } else { 1.5 }));
+        }
+        if max(mtl.ks) > 0. && (matches!(mtl.illum, 3 | 5) || max(mtl.ks) > max(mtl.kd)) {
+            // Maps the Phong exponent onto a roughness in [0, 1].
+            let fuzz = (2. / (mtl.ns.max(0.) + 2.)).sqrt();
+            return Ok(Metal::new(mtl.ks, fuzz));
+        }
+        let albedo: Arc<dyn Texture> = match &mtl.map_kd {
+            Some(path) => ImageTexture::new(path)
+                .map_err(|err| format!("cannot load texture '{}': {}", path.display(), err))?,
+            None => SolidColor::new(mtl.kd),
+        };
+        Ok(Lamberian::new(albedo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        hittable::HitRecord,
+        material::Lobe,
+        random::{Random, SampleRng},
+        ray::Ray,
+    };
+
+    /// Writes `files` to a directory of their own and loads the first one.
+    fn load(
+        test: &str,
+        files: &[(&str, &str)],
+        default_material: Arc<dyn Material>,
+    ) -> Result<Arc<BvhNode>, Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!(
+            "raytracing_obj_test_{}_{}",
+            std::process::id(),
+            test
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        RgbImage::from_pixel(1, 1, Rgb([64, 128, 255]))
+            .save(dir.join("texture.png"))
+            .unwrap();
+        let loaded = ObjLoader::load(&dir.join(files[0].0), default_material);
+        fs::remove_dir_all(&dir).unwrap();
+        loaded
+    }
+
+    fn gray() -> Arc<dyn Material> {
+        Lamberian::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn random() -> Random<f64> {
+        Random::new(SampleRng::new(0), Uniform::new(0., 1.))
+    }
+
+    /// Hit of a ray coming down the z axis at `(x, y)`.
+    fn hit(object: &dyn Hittable, x: f64, y: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.), 0.);
+        object.hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut random())
+    }
+
+    #[test]
+    fn indices_count_from_one_or_back_from_the_last_vertex() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   v 2 0 0\nv 3 0 0\nv 2 1 0\n\
+                   vn 0 0 1\n\
+                   f 1//1 2//-1 3//1\n\
+                   f -3 -2 -1\n";
+        let mesh = load("indices", &[("mesh.obj", obj)], gray()).unwrap();
+        for x in [0.25, 2.25] {
+            let rec = hit(mesh.as_ref(), x, 0.25).unwrap();
+            assert!(Vec3::length(rec.p - Vec3::new(x, 0.25, 0.)) < 1e-12);
+        }
+        assert!(hit(mesh.as_ref(), 1.25, 0.25).is_none());
+
+        for (face, message) in [
+            ("f 1 2 7", "mesh.obj:4: index 7 out of range"),
+            ("f -7 2 3", "mesh.obj:4: index -7 out of range"),
+            ("f 1/1 2/1 3/1", "mesh.obj:4: index 1 out of range"),
+            ("f 1//2 2//1 3//1", "mesh.obj:4: index 2 out of range"),
+            ("f 0 1 2", "mesh.obj:4: index 0 out of range"),
+            ("f 1 2", "mesh.obj:4: face needs at least 3 vertices"),
+        ] {
+            let obj = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{}\n", face);
+            let Err(err) = load("index_errors", &[("mesh.obj", &obj)], gray()) else {
+                panic!("'{}' loaded", face);
+            };
+            assert!(
+                err.to_string().ends_with(message),
+                "'{}' gave '{}'",
+                face,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn mtl_materials_map_onto_ours() {
+        let mtl = "newmtl diffuse\nKd 0.2 0.4 0.6\n\
+                   newmtl textured\nKd 1 1 1\nmap_Kd -s 1 1 1 texture.png\n\
+                   newmtl metal\nKd 0.1 0.1 0.1\nKs 0.9 0.8 0.7\nNs 200\n\
+                   newmtl glass\nKd 0.1 0.1 0.1\nNi 2\nd 0.5\n\
+                   newmtl light\nKe 3 2 1\n";
+        // A unit square per material, side by side along x.
+        let mut obj = String::from("mtllib scene.mtl\n");
+        for (i, name) in ["diffuse", "textured", "metal", "glass", "light"]
+            .iter()
+            .enumerate()
+        {
+            let x = 2 * i;
+            obj += &format!(
+                "v {} 0 0\nv {} 0 0\nv {} 1 0\nv {} 1 0\nusemtl {}\nf -4 -3 -2 -1\n",
+                x,
+                x + 1,
+                x + 1,
+                x,
+                name
+            );
+        }
+        let scene = load(
+            "materials",
+            &[("scene.obj", &obj), ("scene.mtl", mtl)],
+            gray(),
+        )
+        .unwrap();
+        let material_at = |x: f64| {
+            let rec = hit(scene.as_ref(), x, 0.5).unwrap();
+            (rec.material.clone().unwrap(), rec)
+        };
+        let mut rng = random();
+
+        let (diffuse, rec) = material_at(0.5);
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 1.), Vec3::new(0., 0., -1.), 0.);
+        assert_eq!(diffuse.albedo(&rec), Vec3::new(0.2, 0.4, 0.6));
+        assert_eq!(
+            diffuse.sample(&mut rng, &ray, &rec).unwrap().lobe,
+            Lobe::Diffuse
+        );
+
+        let (textured, rec) = material_at(2.5);
+        let texel = Vec3::new(
+            Vec3::srgb_eotf(64. / 255.),
+            Vec3::srgb_eotf(128. / 255.),
+            1.,
+        );
+        assert!(Vec3::length(textured.albedo(&rec) - texel) < 1e-6);
+
+        let (metal, rec) = material_at(4.5);
+        assert!(!metal.is_delta());
+        assert_eq!(metal.albedo(&rec), Vec3::new(0.9, 0.8, 0.7));
+        assert_eq!(
+            metal.sample(&mut rng, &ray, &rec).unwrap().lobe,
+            Lobe::Glossy
+        );
+
+        // Refraction at 45 degrees into glass of index 2 follows Snell's law.
+        let (glass, rec) = material_at(6.5);
+        assert!(glass.is_delta());
+        let ray = Ray::new(rec.p + Vec3::new(-1., 0., 1.), Vec3::new(1., 0., -1.), 0.);
+        let refracted = (0..100)
+            .filter_map(|_| glass.sample(&mut rng, &ray, &rec))
+            .find(|sample| sample.lobe == Lobe::Transmission)
+            .unwrap();
+        let sine = (1. - refracted.direction.z_b.powi(2)).sqrt();
+        assert!((sine - 0.5f64.sqrt() / 2.).abs() < 1e-9);
+
+        let (light, rec) = material_at(8.5);
+        assert!(light.is_emissive());
+        assert_eq!(light.emitted(rec.u, rec.v, rec.p), Vec3::new(3., 2., 1.));
+    }
+
+    #[test]
+    fn every_group_becomes_one_mesh() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                   g first\nf 1 2 3\n\
+                   g second\nf 1 2 3\nf 3 2 1\n\
+                   o third\nf 1 2 3\n\
+                   g first\nf 3 2 1\n";
+        // Emissive meshes are the lights that the BVH hands out, one per mesh.
+        let light = DiffuseLight::new(SolidColor::new(Vec3::new(1., 1., 1.)));
+        let scene = load("groups", &[("groups.obj", obj)], light).unwrap();
+        let mut meshes = vec![];
+        scene.collect_lights(&mut meshes);
+        assert_eq!(meshes.len(), 3);
+    }
+}
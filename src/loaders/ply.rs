@@ -0,0 +1,539 @@
+use std::{error::Error, fs, path::Path, str::SplitAsciiWhitespace, sync::Arc};
+
+use crate::{material::Material, objects::triangle_mesh::TriangleMesh, vec3::Vec3};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::UInt8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::UInt16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::UInt32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The element data following the header, read one value at a time.
+enum Body<'a> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        position: usize,
+        big_endian: bool,
+    },
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("unexpected end of data")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", token))
+            }
+            Body::Binary {
+                bytes,
+                position,
+                big_endian,
+            } => {
+                let size = scalar.size();
+                let data = bytes
+                    .get(*position..*position + size)
+                    .ok_or("unexpected end of data")?;
+                *position += size;
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(data);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::Int8 => buffer[0] as i8 as f64,
+                    Scalar::UInt8 => buffer[0] as f64,
+                    Scalar::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    Scalar::Int32 => {
+                        i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::UInt32 => {
+                        u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::Float32 => {
+                        f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+                    }
+                    Scalar::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+/// Positions of the vertex properties we understand within an element.
+#[derive(Default)]
+struct VertexLayout {
+    position: [Option<usize>; 3],
+    normal: [Option<usize>; 3],
+    uv: [Option<usize>; 2],
+    color: [Option<usize>; 3],
+    /// Integer color channels are scaled down to `0..=1`.
+    color_scale: f64,
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Self {
+        let mut layout = Self {
+            color_scale: 1.,
+            ..Default::default()
+        };
+        for (index, property) in element.properties.iter().enumerate() {
+            let slot = match property.name.as_str() {
+                "x" => &mut layout.position[0],
+                "y" => &mut layout.position[1],
+                "z" => &mut layout.position[2],
+                "nx" => &mut layout.normal[0],
+                "ny" => &mut layout.normal[1],
+                "nz" => &mut layout.normal[2],
+                "u" | "s" | "texture_u" | "texture_s" => &mut layout.uv[0],
+                "v" | "t" | "texture_v" | "texture_t" => &mut layout.uv[1],
+                "red" | "r" | "diffuse_red" => &mut layout.color[0],
+                "green" | "g" | "diffuse_green" => &mut layout.color[1],
+                "blue" | "b" | "diffuse_blue" => &mut layout.color[2],
+                _ => continue,
+            };
+            *slot = Some(index);
+        }
+        if let Some(red) = layout.color[0] {
+            layout.color_scale = match element.properties[red].kind {
+                PropertyKind::Scalar(Scalar::Int8 | Scalar::UInt8) => 1. / 255.,
+                PropertyKind::Scalar(Scalar::Int16 | Scalar::UInt16) => 1. / 65535.,
+                _ => 1.,
+            };
+        }
+        layout
+    }
+
+    fn get<const N: usize>(slots: &[Option<usize>; N], values: &[f64]) -> Option<[f64; N]> {
+        let mut out = [0.; N];
+        for (out, slot) in out.iter_mut().zip(slots) {
+            *out = values[(*slot)?];
+        }
+        Some(out)
+    }
+}
+
+pub struct PlyLoader {}
+
+impl PlyLoader {
+    /// Loads a Stanford PLY file (ASCII or binary) into a single `TriangleMesh`.
+    ///
+    /// Per-vertex normals, texture coordinates and colors are kept when the file has
+    /// them; a `VertexColor` texture in `material` picks the colors up. Polygons are
+    /// split into triangle fans.
+    pub fn load(
+        path: &Path,
+        material: Arc<dyn Material>,
+    ) -> Result<Arc<TriangleMesh>, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        Self::parse(&bytes, material).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    fn parse(bytes: &[u8], material: Arc<dyn Material>) -> Result<Arc<TriangleMesh>, String> {
+        let (format, elements, body_start) = Self::parse_header(bytes)?;
+        let mut body = match format {
+            Format::Ascii => Body::Ascii(
+                std::str::from_utf8(&bytes[body_start..])
+                    .map_err(|_| "ASCII data is not valid UTF-8")?
+                    .split_ascii_whitespace(),
+            ),
+            _ => Body::Binary {
+                bytes: &bytes[body_start..],
+                position: 0,
+                big_endian: format == Format::BinaryBigEndian,
+            },
+        };
+
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut uvs = vec![];
+        let mut colors = vec![];
+        let mut indices: Vec<[u32; 3]> = vec![];
+        let mut values = vec![];
+        let mut list = vec![];
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => {
+                    let layout = VertexLayout::new(element);
+                    positions.reserve_exact(element.count);
+                    for _ in 0..element.count {
+                        values.clear();
+                        for property in &element.properties {
+                            match property.kind {
+                                PropertyKind::Scalar(scalar) => values.push(body.read(scalar)?),
+                                // Lists on vertices carry nothing we use.
+                                PropertyKind::List { count, item } => {
+                                    Self::read_list(&mut body, count, item, &mut list)?;
+                                    values.push(0.);
+                                }
+                            }
+                        }
+                        let [x, y, z] = VertexLayout::get(&layout.position, &values)
+                            .ok_or("vertex element has no x, y and z")?;
+                        positions.push(Vec3::new(x, y, z));
+                        if let Some([x, y, z]) = VertexLayout::get(&layout.normal, &values) {
+                            normals.push(Vec3::new(x, y, z));
+                        }
+                        if let Some([u, v]) = VertexLayout::get(&layout.uv, &values) {
+                            uvs.push((u, v));
+                        }
+                        if let Some(color) = VertexLayout::get(&layout.color, &values) {
+                            let [r, g, b] = color.map(|c| c * layout.color_scale);
+                            colors.push(Vec3::new(r, g, b));
+                        }
+                    }
+                }
+                "face" => {
+                    indices.reserve(element.count);
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            match property.kind {
+                                PropertyKind::List { count, item }
+                                    if property.name == "vertex_indices"
+                                        || property.name == "vertex_index" =>
+                                {
+                                    Self::read_list(&mut body, count, item, &mut list)?;
+                                    for i in 1..list.len().saturating_sub(1) {
+                                        indices.push([list[0], list[i], list[i + 1]]);
+                                    }
+                                }
+                                PropertyKind::List { count, item } => {
+                                    Self::read_list(&mut body, count, item, &mut list)?
+                                }
+                                PropertyKind::Scalar(scalar) => {
+                                    body.read(scalar)?;
+                                }
+                            }
+                        }
+                    }
+                }
+                // Other elements (edges, materials, ...) are skipped over.
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            match property.kind {
+                                PropertyKind::Scalar(scalar) => {
+                                    body.read(scalar)?;
+                                }
+                                PropertyKind::List { count, item } => {
+                                    Self::read_list(&mut body, count, item, &mut list)?
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if indices.is_empty() {
+            return Err("file has no faces".into());
+        }
+        if let Some(index) = indices
+            .iter()
+            .flatten()
+            .find(|&&i| i as usize >= positions.len())
+        {
+            return Err(format!("vertex index {} out of range", index));
+        }
+        let vertex_count = positions.len();
+        let complete = |len: usize| len == vertex_count && len > 0;
+        Ok(TriangleMesh::new(
+            positions,
+            complete(normals.len()).then_some(normals),
+            complete(uvs.len()).then_some(uvs),
+            complete(colors.len()).then_some(colors),
+            indices,
+            material,
+        ))
+    }
+
+    fn read_list(
+        body: &mut Body,
+        count: Scalar,
+        item: Scalar,
+        list: &mut Vec<u32>,
+    ) -> Result<(), String> {
+        list.clear();
+        let count = body.read(count)?;
+        if count < 0. {
+            return Err(format!("invalid list length {}", count));
+        }
+        for _ in 0..count as usize {
+            let value = body.read(item)?;
+            if value < 0. {
+                return Err(format!("invalid vertex index {}", value));
+            }
+            list.push(value as u32);
+        }
+        Ok(())
+    }
+
+    /// Returns the format, the declared elements and the offset of the first data byte.
+    fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize), String> {
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        let mut offset = 0;
+        let mut line_number = 0;
+        loop {
+            let rest = &bytes[offset..];
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or("header has no 'end_header'")?;
+            let line = String::from_utf8_lossy(&rest[..end]);
+            offset += end + 1;
+            line_number += 1;
+            let error = |message: String| format!("header line {}: {}", line_number, message);
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if line_number == 1 {
+                if parts != ["ply"] {
+                    return Err("not a PLY file".into());
+                }
+                continue;
+            }
+            match parts.as_slice() {
+                ["format", name, _version] => {
+                    format = Some(match *name {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(error(format!("unknown format '{}'", name))),
+                    })
+                }
+                ["comment", ..] | ["obj_info", ..] | [] => {}
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| error(format!("invalid element count '{}'", count)))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count, item, name] => {
+                    let scalar = |name: &str| {
+                        Scalar::parse(name).ok_or_else(|| error(format!("unknown type '{}'", name)))
+                    };
+                    let kind = PropertyKind::List {
+                        count: scalar(count)?,
+                        item: scalar(item)?,
+                    };
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| error("property outside of an element".into()))?
+                        .properties
+                        .push(Property {
+                            name: name.to_string(),
+                            kind,
+                        });
+                }
+                ["property", scalar, name] => {
+                    let kind = PropertyKind::Scalar(
+                        Scalar::parse(scalar)
+                            .ok_or_else(|| error(format!("unknown type '{}'", scalar)))?,
+                    );
+                    elements
+                        .last_mut()
+                        .ok_or_else(|| error("property outside of an element".into()))?
+                        .properties
+                        .push(Property {
+                            name: name.to_string(),
+                            kind,
+                        });
+                }
+                ["end_header"] => break,
+                _ => return Err(error(format!("unexpected '{}'", line.trim()))),
+            }
+        }
+        let format = format.ok_or("header has no 'format' line")?;
+        Ok((format, elements, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        hittable::HitRecord,
+        material::lamberian::Lamberian,
+        random::{Random, SampleRng},
+        ray::Ray,
+        texture::{solid_color::SolidColor, vertex_color::VertexColor},
+        Hittable,
+    };
+
+    /// A unit square with tilted normals and a red, a green, a blue and a white corner.
+    const POSITIONS: [[f32; 3]; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+    const NORMAL: [f32; 3] = [0., 0.6, 0.8];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+    fn header(format: &str, vertex_count: usize) -> String {
+        format!(
+            "ply\nformat {} 1.0\ncomment a colored square\n\
+             element vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property float nx\nproperty float ny\nproperty float nz\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format, vertex_count
+        )
+    }
+
+    fn ascii(face: &str) -> Vec<u8> {
+        let mut source = header("ascii", 4);
+        for (position, color) in POSITIONS.iter().zip(COLORS) {
+            let [x, y, z] = position;
+            let [nx, ny, nz] = NORMAL;
+            let [r, g, b] = color;
+            source += &format!("{x} {y} {z} {nx} {ny} {nz} {r} {g} {b}\n");
+        }
+        source += face;
+        source.into_bytes()
+    }
+
+    fn binary(big_endian: bool, face: &[i32]) -> Vec<u8> {
+        let format = match big_endian {
+            true => "binary_big_endian",
+            false => "binary_little_endian",
+        };
+        let mut bytes = header(format, 4).into_bytes();
+        let float = |bytes: &mut Vec<u8>, value: f32| match big_endian {
+            true => bytes.extend(value.to_be_bytes()),
+            false => bytes.extend(value.to_le_bytes()),
+        };
+        for (position, color) in POSITIONS.iter().zip(COLORS) {
+            for value in position.iter().chain(&NORMAL) {
+                float(&mut bytes, *value);
+            }
+            bytes.extend(color);
+        }
+        bytes.push(face.len() as u8);
+        for index in face {
+            match big_endian {
+                true => bytes.extend(index.to_be_bytes()),
+                false => bytes.extend(index.to_le_bytes()),
+            }
+        }
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Arc<TriangleMesh>, String> {
+        let material = Lamberian::new(VertexColor::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5))));
+        PlyLoader::parse(bytes, material)
+    }
+
+    fn hit(mesh: &TriangleMesh, x: f64, y: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.), 0.);
+        let mut rng = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        mesh.hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut rng)
+    }
+
+    #[test]
+    fn ascii_and_binary_files_load_the_same_mesh() {
+        for (format, bytes) in [
+            ("ascii", ascii("4 0 1 2 3\n")),
+            ("little endian", binary(false, &[0, 1, 2, 3])),
+            ("big endian", binary(true, &[0, 1, 2, 3])),
+        ] {
+            let mesh = parse(&bytes).unwrap_or_else(|err| panic!("{}: {}", format, err));
+            assert_eq!(mesh.face_count(), 2, "{}", format);
+            // Weights 0.25, 0.5 and 0.25 of the red, green and blue corners.
+            let rec = hit(&mesh, 0.75, 0.25).unwrap();
+            assert!(Vec3::length(rec.p - Vec3::new(0.75, 0.25, 0.)) < 1e-6);
+            assert!(
+                Vec3::length(rec.normal - Vec3::new(0., 0.6, 0.8)) < 1e-6,
+                "{}",
+                format
+            );
+            let color = Vec3::new(0.25, 0.5, 0.25);
+            assert!(
+                Vec3::length(rec.color.unwrap() - color) < 1e-6,
+                "{}",
+                format
+            );
+            let albedo = rec.material.as_ref().unwrap().albedo(&rec);
+            assert!(Vec3::length(albedo - color) < 1e-6, "{}", format);
+            assert!(hit(&mesh, 1.25, 0.5).is_none());
+        }
+    }
+
+    #[test]
+    fn bad_vertex_indices_are_reported() {
+        let mut truncated = binary(false, &[0, 1, 2]);
+        truncated.truncate(truncated.len() - 3);
+        for (bytes, message) in [
+            (ascii("3 0 1 7\n"), "vertex index 7 out of range"),
+            (ascii("3 0 -1 2\n"), "invalid vertex index -1"),
+            (binary(false, &[0, 1, 4]), "vertex index 4 out of range"),
+            (binary(true, &[0, -2, 1]), "invalid vertex index -2"),
+            (ascii("3 0 1\n"), "unexpected end of data"),
+            (truncated, "unexpected end of data"),
+            (
+                b"ply\nformat ascii 1.0\n".to_vec(),
+                "header has no 'end_header'",
+            ),
+        ] {
+            match parse(&bytes) {
+                Ok(_) => panic!("expected '{}'", message),
+                Err(err) => assert_eq!(err, message),
+            }
+        }
+    }
+}
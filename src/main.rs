@@ -1,103 +1,268 @@
-mod aabb;
-mod bvh_node;
-mod camera;
-mod hittable;
-mod image_env_builder;
-mod material;
-mod objects;
-mod perlin;
-mod random;
-mod ray;
-mod texture;
-mod vec3;
+mod cli;
 
-use camera::Camera;
-
-use hittable::HittableList;
-use image::{codecs::png::PngEncoder, ColorType, ImageEncoder};
-use material::Material;
-use objects::Hittable;
+use cli::CliArgs;
 use rand::distributions::Uniform;
-use random::Random;
-use ray::Ray;
-use rayon::{iter::IntoParallelIterator, iter::ParallelIterator};
-use std::{error::Error, fs::File, io::BufWriter, mem::swap, sync::Arc, time::Instant};
-use vec3::Vec3;
+use raytracing_in_one_week::{
+    aov::Aov,
+    checkpoint::Checkpoint,
+    denoiser::Denoiser,
+    film::Film,
+    image_env_builder::ImageEnvBuilder,
+    loaders::gltf::GltfLoader,
+    output::{ImageFormat, RenderMetadata},
+    random::{Random, SampleRng},
+    scene_file::SceneFile,
+    tone_map::ToneMapper,
+    RenderSettings, Renderer,
+};
+use std::{error::Error, fs, path::Path, time::Instant};
 
 use mimalloc::MiMalloc;
 
-use crate::image_env_builder::ImageEnvBuilder;
-
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
-const SAMPLES_PER_PIXEL: u32 = 1000;
-const MAX_DEPTH: u8 = 50;
-const IMAGE_WIDTH: u32 = 1280;
-const ASPECT_RATIO: f64 = 16. / 9.;
-const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
-
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() != 2 {
-        println!("Usage: ./raytracer [choice]");
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let args = match CliArgs::parse(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
         return Ok(());
     }
+    let (scene, output, settings, pass_samples, resumed) = match &args.resume {
+        Some(path) => {
+            let checkpoint = Checkpoint::load(Path::new(path))?;
+            println!(
+                "Resuming {} from {:.1} spp",
+                checkpoint.scene,
+                average_samples(&checkpoint.film)
+            );
+            (
+                checkpoint.scene,
+                checkpoint.output,
+                checkpoint.settings.threads(args.threads),
+                Some(checkpoint.samples_per_pass),
+                Some(checkpoint.film),
+            )
+        }
+        None => {
+            let mut settings = RenderSettings::new()
+                .width(args.width)
+                .height(args.height)
+                .samples_per_pixel(args.samples_per_pixel)
+                .max_depth(args.max_depth)
+                .bounces(args.bounces)
+                .roulette_depth(args.roulette_depth)
+                .threads(args.threads)
+                .seed(args.seed.unwrap_or_else(rand::random))
+                .filter(args.filter)
+                .mis(args.mis)
+                .integrator(args.integrator.unwrap_or_default());
+            if let Some(adaptive) = args.adaptive {
+                settings = settings.adaptive(adaptive);
+            }
+            (
+                args.scene.clone(),
+                args.output.clone(),
+                settings,
+                args.pass_samples,
+                None,
+            )
+        }
+    };
+    let seed = settings.seed;
+    println!("Seed: {}", seed);
+
     // World
-    let choice: u32 = args[1].parse()?;
-    let mut rng = rand::thread_rng();
-    let mut random = Random::new(&mut rng, Uniform::new(0.0, 1.0));
+    let mut random = Random::new(SampleRng::new(seed), Uniform::new(0.0, 1.0));
     // Camera
-    let (camera, world) = ImageEnvBuilder::build(choice, &mut random);
-
-    // Image
-    let mut img_buf = image::RgbImage::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+    let extension = Path::new(&scene)
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let loaded_scene = if let Ok(choice) = scene.parse::<u32>() {
+        ImageEnvBuilder::build(choice, settings.aspect_ratio(), &mut random)
+    } else if extension == "gltf" || extension == "glb" {
+        GltfLoader::load_scene(Path::new(&scene), settings.aspect_ratio())?
+    } else {
+        SceneFile::load(Path::new(&scene), settings.aspect_ratio(), &mut random)?
+    };
+    // The scene may choose the integrator, unless the command line or the checkpoint
+    // being resumed already did.
+    let settings = match loaded_scene.integrator {
+        Some(integrator) if args.integrator.is_none() && resumed.is_none() => {
+            settings.integrator(integrator)
+        }
+        _ => settings,
+    };
 
     // Render
     println!("Rendering...");
 
-    let mut progress: u32 = 0;
+    let checkpoint_path = Path::new(&output).with_extension("checkpoint");
+    let film = resumed.unwrap_or_else(|| Film::new(settings.width, settings.height));
+    let mut progress = (film.sample_count() * 100
+        / (film.pixel_count() * settings.samples_per_pixel as u64)) as u32;
     let mut time1 = Instant::now();
-    let mut random = Random::new(&mut rng, Uniform::new(0.0, 1.0));
-    (0..IMAGE_WIDTH * IMAGE_HEIGHT).for_each(|i| {
-        let w = i % IMAGE_WIDTH;
-        let h = i / IMAGE_WIDTH;
-        // TODO: workaround to invert image; investigate why is it needed?
-        let pixel_color = work(
-            IMAGE_WIDTH - w,
-            IMAGE_WIDTH,
-            h,
-            IMAGE_HEIGHT,
-            camera,
-            &world,
-            &mut random,
-        );
-        // TODO: workaround to invert image; investigate why is it needed?
-        img_buf.put_pixel(
-            IMAGE_WIDTH - w - 1,
-            IMAGE_HEIGHT - h - 1,
-            pixel_color.into(),
-        );
-        display_progress(&mut progress, &mut time1, h, w);
-    });
+    let start = Instant::now();
+    let metadata = |samples_per_pixel| RenderMetadata {
+        scene: scene.clone(),
+        samples_per_pixel,
+        max_depth: settings.max_depth,
+        width: settings.width,
+        height: settings.height,
+        seed: settings.seed,
+        render_time: start.elapsed(),
+    };
+    let renderer = Renderer::new(loaded_scene, settings)?;
+    let on_progress = |done, total| display_progress(&mut progress, &mut time1, done, total);
+    // Sample maps and AOVs are data rather than pictures, so they are not tone mapped.
+    let data_output = args.image_output.tone_mapper(ToneMapper::default());
+    let mut passes = 0;
+    let mut last_checkpoint = Instant::now();
+    let mut variance = vec![];
+    let mut samples_per_pixel = 0.;
+    let framebuffer = renderer.resume_progressive(
+        film,
+        pass_samples.unwrap_or_else(|| settings.single_pass_samples()),
+        |film, finished| {
+            samples_per_pixel = average_samples(film);
+            if finished {
+                if settings.adaptive.is_some() {
+                    println!("Adaptive sampling: {:.1} spp", samples_per_pixel);
+                }
+                if let Some(path) = &args.sample_map {
+                    let metadata = metadata(samples_per_pixel);
+                    data_output.save(&film.sample_map(), Path::new(path), Some(&metadata))?;
+                    println!("Sample map written to {}", path);
+                }
+                if args.denoiser.is_some() {
+                    variance = film.luminance_variance();
+                }
+                return Ok(());
+            }
+            let Some(samples_per_pass) = pass_samples else {
+                return Ok(());
+            };
+            passes += 1;
+            let due_by_passes = args
+                .checkpoint_passes
+                .is_some_and(|every| passes % every == 0);
+            let due_by_time = args
+                .checkpoint_seconds
+                .is_some_and(|seconds| last_checkpoint.elapsed().as_secs_f64() >= seconds);
+            // The final image is written below anyway.
+            if due_by_passes || due_by_time {
+                args.image_output.save(
+                    &film.to_framebuffer(),
+                    Path::new(&output),
+                    Some(&metadata(samples_per_pixel)),
+                )?;
+                Checkpoint {
+                    scene: scene.clone(),
+                    output: output.clone(),
+                    settings,
+                    samples_per_pass,
+                    film: film.clone(),
+                }
+                .save(&checkpoint_path)?;
+                println!(
+                    "Checkpoint: {:.1} spp written to {} and {}",
+                    samples_per_pixel,
+                    output,
+                    checkpoint_path.display()
+                );
+                last_checkpoint = Instant::now();
+            }
+            Ok(())
+        },
+        on_progress,
+    )?;
+
+    // The denoiser's guides are rendered along with the AOVs asked for.
+    let mut aovs = args.aovs.clone();
+    if args.denoiser.is_some() {
+        for guide in Denoiser::GUIDES {
+            if !aovs.contains(&guide) {
+                aovs.push(guide);
+            }
+        }
+    }
+    let aov_buffers = if aovs.is_empty() {
+        vec![]
+    } else {
+        println!("Rendering AOVs...");
+        renderer.render_aovs(&aovs)?
+    };
+    let aov_buffer = |aov| &aov_buffers[aovs.iter().position(|&other| other == aov).unwrap()];
+    let framebuffer = match args.denoiser {
+        Some(denoiser) => {
+            println!("Denoising...");
+            denoiser.denoise(
+                &framebuffer,
+                &variance,
+                aov_buffer(Aov::Albedo),
+                aov_buffer(Aov::Normal),
+                aov_buffer(Aov::Depth),
+            )
+        }
+        None => framebuffer,
+    };
 
     // Saving
     println!("Saving...");
-    let file_ppm = File::create(format!("./render/render{}.png", choice))?;
-    let buf_writer = BufWriter::new(file_ppm);
-    let enc = PngEncoder::new(buf_writer);
-    enc.write_image(&img_buf, IMAGE_WIDTH, IMAGE_HEIGHT, ColorType::Rgb8)?;
+    let metadata = metadata(samples_per_pixel);
+    args.image_output
+        .save(&framebuffer, Path::new(&output), Some(&metadata))?;
+    if checkpoint_path.exists() {
+        fs::remove_file(&checkpoint_path)?;
+    }
+    for aov in &args.aovs {
+        let path = aov_path(&output, *aov);
+        let buffer = aov_buffer(*aov);
+        // High dynamic range formats keep the raw values.
+        if ImageFormat::from_path(Path::new(&path))?.is_hdr() {
+            data_output.save(buffer, Path::new(&path), Some(&metadata))?;
+        } else {
+            data_output.save(&aov.to_display(buffer), Path::new(&path), Some(&metadata))?;
+        }
+        println!("{} written to {}", aov, path);
+    }
 
     println!("Done!");
     Ok(())
 }
 
-fn display_progress(progress: &mut u32, time1: &mut Instant, h: u32, w: u32) {
-    let progress2 = (w + (IMAGE_WIDTH * h) * 100) / (IMAGE_HEIGHT * IMAGE_WIDTH);
+/// Average number of samples per pixel taken so far.
+fn average_samples(film: &Film) -> f64 {
+    film.sample_count() as f64 / film.pixel_count() as f64
+}
+
+/// `render/render1.png` becomes `render/render1.albedo.png`.
+fn aov_path(output: &str, aov: Aov) -> String {
+    let path = Path::new(output);
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_extension(format!("{}.{}", aov, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn display_progress(progress: &mut u32, time1: &mut Instant, done: u64, total: u64) {
+    let progress2 = (done * 100 / total) as u32;
     if progress2 > *progress {
         let time2 = Instant::now();
         let duration_since = time2.duration_since(*time1);
-        let eta = duration_since * (100 - progress2);
+        // A finished tile can advance the progress by more than one percent.
+        let step = progress2 - *progress;
+        let eta = duration_since * (100 - progress2) / step;
+        let rays_per_sec = total as f64 * 0.01 * step as f64 / duration_since.as_secs_f64();
         if eta.as_secs() > 3600 {
             println!(
                 "{0}% - ETA: {1} h. {2} min. {3} sec. ({4:.2} rays/sec)",
@@ -105,8 +270,7 @@ fn display_progress(progress: &mut u32, time1: &mut Instant, h: u32, w: u32) {
                 eta.as_secs() / 3600,
                 (eta.as_secs() % 3600) / 60,
                 eta.as_secs() % 60,
-                SAMPLES_PER_PIXEL as f64 / duration_since.as_secs_f64()
-                    * (IMAGE_WIDTH as f64 * IMAGE_HEIGHT as f64 * 0.01)
+                rays_per_sec
             );
         } else if eta.as_secs() > 60 {
             println!(
@@ -114,16 +278,14 @@ fn display_progress(progress: &mut u32, time1: &mut Instant, h: u32, w: u32) {
                 progress2,
                 eta.as_secs() / 60,
                 eta.as_secs() % 60,
-                SAMPLES_PER_PIXEL as f64 / duration_since.as_secs_f64()
-                    * (IMAGE_WIDTH as f64 * IMAGE_HEIGHT as f64 * 0.01)
+                rays_per_sec
             );
         } else {
             println!(
                 "{0}% - ETA: {1} sec. ({2:.2} rays/sec)",
                 progress2,
                 eta.as_secs() % 60,
-                SAMPLES_PER_PIXEL as f64 / duration_since.as_secs_f64()
-                    * (IMAGE_WIDTH as f64 * IMAGE_HEIGHT as f64 * 0.01),
+                rays_per_sec,
             );
         }
 
@@ -131,64 +293,3 @@ fn display_progress(progress: &mut u32, time1: &mut Instant, h: u32, w: u32) {
         *time1 = Instant::now();
     }
 }
-
-fn work(
-    width: u32,
-    image_width: u32,
-    height: u32,
-    image_height: u32,
-    camera: Camera,
-    world: &Arc<HittableList>,
-    rng: &mut Random<f64>,
-) -> Vec3 {
-    let u = (width as f64 + rng.random(None, None)) / (image_width as f64 - 1.);
-    let v = (height as f64 + rng.random(None, None)) / (image_height as f64 - 1.);
-    (0..SAMPLES_PER_PIXEL)
-        .into_par_iter()
-        .map(|_| {
-            let mut rand = rand::thread_rng();
-            let mut rng = Random::new(&mut rand, Uniform::new(0.0, 1.0));
-            let mut ray = Camera::get_ray(&mut rng, camera, u, v);
-            let mut background = Vec3::new(0., 0., 0.);
-            ray_color_iterative(&mut ray, world, &mut background, &mut rng, MAX_DEPTH)
-        })
-        .reduce(Vec3::default, |a, b| a + b)
-}
-
-fn ray_color_iterative(
-    ray: &mut Ray,
-    hittable_list: &HittableList,
-    background: &mut Vec3,
-    rng: &mut Random<f64>,
-    depth: u8,
-) -> Vec3 {
-    let mut acc = Vec3::new(1., 1., 1.);
-    let mut depth_count = depth;
-    let mut attenuation = Vec3::default();
-    let mut scattered = Ray::default();
-    let mut emitted;
-    loop {
-        if let Some(rec) = hittable_list.hit(ray, f64::MIN_POSITIVE, f64::MAX) {
-            emitted = rec.material.as_ref().emitted(rec.u, rec.v, rec.p);
-            if rec
-                .material
-                .as_ref()
-                .scatter(rng, ray, &rec, &mut attenuation, &mut scattered)
-            {
-                acc = acc * attenuation + emitted;
-                swap(ray, &mut scattered);
-                depth_count = depth_count.saturating_sub(1);
-                if depth_count == 0 {
-                    break;
-                }
-            } else {
-                acc = acc * emitted;
-                break;
-            }
-        } else {
-            acc = acc * *background;
-            break;
-        }
-    }
-    acc
-}
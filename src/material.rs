@@ -1,18 +1,71 @@
 use std::sync::Arc;
 
 use crate::{hittable::HitRecord, random::Random, vec3::Vec3, Ray};
+
+/// Kind of scattering event, which paths have separate bounce limits for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    /// Reflection off metals and glass, polished or not.
+    Glossy,
+    /// Refraction into or out of glass.
+    Transmission,
+    /// Scattering inside a participating medium.
+    Volume,
+}
+
+/// Scattered direction drawn by `Material::sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    /// Unit direction of the scattered ray.
+    pub direction: Vec3,
+    /// BSDF value for `direction`.
+    pub f: Vec3,
+    /// Probability density of having drawn `direction`, per solid angle.
+    pub pdf: f64,
+    /// Whether `direction` comes from a delta (perfectly specular) lobe. Such lobes
+    /// have no density, so `f` and `pdf` are only meaningful as the ratio
+    /// `f * cosine / pdf`, and `eval`/`pdf` never return them.
+    pub delta: bool,
+    pub lobe: Lobe,
+}
+
+/// How light scatters at a surface (a BSDF) or inside a volume (a phase function).
+///
+/// Paths are weighted by `f * cosine / pdf` per bounce, where `f` and `pdf` come from
+/// `sample`, or from `eval` and `pdf` for directions chosen by someone else.
 pub trait Material: Send + Sync {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool;
+    /// Draws a scattered direction for a ray arriving along `ray_in`, or `None` if the
+    /// ray is absorbed.
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample>;
+    /// BSDF value for scattering `ray_in` into `direction`, without delta lobes.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+    /// Density with which `sample` draws `direction`, without delta lobes.
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f64 {
+        0.
+    }
+    /// Whether all of the scattering is in delta lobes, so that `eval` is always zero.
+    fn is_delta(&self) -> bool {
+        false
+    }
+    /// Foreshortening factor for light leaving in `direction`; volumes, which scatter
+    /// with a phase function, have none.
+    fn cosine(&self, rec: &HitRecord, direction: Vec3) -> f64 {
+        Vec3::dot(Vec3::unit_vector(direction), rec.normal).abs()
+    }
     fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
+    /// Color of the surface at `rec`, as written to the albedo AOV.
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::default()
+    }
+    /// Whether objects made of this material are lights worth sampling directly.
+    fn is_emissive(&self) -> bool {
+        false
+    }
 }
 
 pub mod dielectric;
@@ -22,18 +75,41 @@ pub mod lamberian;
 pub mod metal;
 
 impl<T: Material + ?Sized> Material for Option<&Arc<T>> {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample> {
+        self.and_then(|material| material.sample(rng, ray_in, rec))
+    }
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
         if let Some(material) = self {
-            material.scatter(rng, ray_in, rec, attenuation, scattered)
+            material.eval(ray_in, rec, direction)
         } else {
-            false
+            Vec3::default()
+        }
+    }
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        if let Some(material) = self {
+            material.pdf(ray_in, rec, direction)
+        } else {
+            0.
+        }
+    }
+    fn is_delta(&self) -> bool {
+        self.is_some_and(|material| material.is_delta())
+    }
+    fn cosine(&self, rec: &HitRecord, direction: Vec3) -> f64 {
+        if let Some(material) = self {
+            material.cosine(rec, direction)
+        } else {
+            0.
+        }
+    }
+    fn is_emissive(&self) -> bool {
+        self.is_some_and(|material| material.is_emissive())
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        if let Some(material) = self {
+            material.albedo(rec)
+        } else {
+            Vec3::default()
         }
     }
     fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
@@ -44,3 +120,155 @@ impl<T: Material + ?Sized> Material for Option<&Arc<T>> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        material::{
+            dielectric::Dielectric, isotropic::Isotropic, lamberian::Lamberian, metal::Metal,
+        },
+        random::SampleRng,
+        texture::solid_color::SolidColor,
+    };
+
+    const SAMPLES: usize = 400_000;
+
+    fn random(seed: u64) -> Random<f64> {
+        Random::new(SampleRng::new(seed), Uniform::new(0., 1.))
+    }
+
+    /// Hit on the front of a surface facing +z, by a ray coming in at 45 degrees.
+    fn hit() -> (Ray, HitRecord) {
+        let p = Vec3::new(0., 0., 0.);
+        let direction = Vec3::new(1., 0.2, -1.);
+        let rec = HitRecord {
+            p,
+            normal: Vec3::new(0., 0., 1.),
+            material: None,
+            t: 1.,
+            u: 0.5,
+            v: 0.5,
+            front_face: true,
+            color: None,
+        };
+        (Ray::new(p - direction, direction, 0.), rec)
+    }
+
+    /// Direction drawn uniformly from the unit sphere, independently of `Vec3`'s samplers.
+    fn uniform_direction(rng: &mut Random<f64>) -> Vec3 {
+        let z = 1. - 2. * rng.random(None, None);
+        let phi = 2. * PI * rng.random(None, None);
+        let r = (1. - z * z).max(0.).sqrt();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Checks that `sample` agrees with `eval` and `pdf`, and that it really draws
+    /// directions with the density `pdf` reports: the mean of a test function over the
+    /// samples (counting absorbed ones as zero) must match its integral against `pdf`.
+    fn check_consistency(name: &str, material: &dyn Material) {
+        let (ray_in, rec) = hit();
+        let axis = Vec3::unit_vector(Vec3::new(0.3, -0.5, 0.8));
+        let test = |direction: Vec3| (1. + Vec3::dot(direction, axis)).powi(2);
+
+        let mut rng = random(1);
+        let mut sampled = 0.;
+        let mut scattered = 0;
+        for _ in 0..SAMPLES {
+            let Some(sample) = material.sample(&mut rng, &ray_in, &rec) else {
+                continue;
+            };
+            assert!(!sample.delta, "{}: unexpected delta sample", name);
+            let pdf = material.pdf(&ray_in, &rec, sample.direction);
+            let f = material.eval(&ray_in, &rec, sample.direction);
+            assert!(
+                (sample.pdf - pdf).abs() <= 1e-9 * pdf,
+                "{}: pdf mismatch",
+                name
+            );
+            assert!(
+                Vec3::length(sample.f - f) <= 1e-9 * Vec3::length(f),
+                "{}: f mismatch",
+                name
+            );
+            assert!(pdf > 0., "{}: sampled a direction of zero density", name);
+            sampled += test(sample.direction);
+            scattered += 1;
+        }
+        sampled /= SAMPLES as f64;
+
+        let mut rng = random(2);
+        let mut integral = 0.;
+        let mut total = 0.;
+        for _ in 0..SAMPLES {
+            let direction = uniform_direction(&mut rng);
+            let pdf = material.pdf(&ray_in, &rec, direction) * 4. * PI;
+            integral += test(direction) * pdf;
+            total += pdf;
+        }
+        integral /= SAMPLES as f64;
+        total /= SAMPLES as f64;
+
+        let scattered = scattered as f64 / SAMPLES as f64;
+        assert!(
+            (total - scattered).abs() < 0.02,
+            "{}: pdf integrates to {}, but {} of the samples scatter",
+            name,
+            total,
+            scattered
+        );
+        assert!(
+            (integral - sampled).abs() < 0.02 * sampled,
+            "{}: samples average {}, the pdf gives {}",
+            name,
+            sampled,
+            integral
+        );
+    }
+
+    #[test]
+    fn sampling_matches_the_pdf() {
+        let gray = SolidColor::new(Vec3::new(0.5, 0.5, 0.5));
+        check_consistency("lambertian", Lamberian::new(gray.clone()).as_ref());
+        check_consistency("isotropic", Isotropic::new(gray).as_ref());
+        for fuzz in [0.5, 1.] {
+            let metal = Metal::new(Vec3::new(0.9, 0.9, 0.9), fuzz);
+            check_consistency(&format!("metal with fuzz {}", fuzz), metal.as_ref());
+        }
+    }
+
+    #[test]
+    fn lambertian_samples_are_weighted_by_the_albedo() {
+        let albedo = Vec3::new(0.2, 0.5, 0.8);
+        let material = Lamberian::new(SolidColor::new(albedo));
+        let (ray_in, rec) = hit();
+        let mut rng = random(3);
+        for _ in 0..1000 {
+            let sample = material.sample(&mut rng, &ray_in, &rec).unwrap();
+            let weight = sample.f * material.cosine(&rec, sample.direction) / sample.pdf;
+            assert!(Vec3::length(weight - albedo) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn specular_materials_only_have_delta_lobes() {
+        let (ray_in, rec) = hit();
+        let mut rng = random(4);
+        let materials: [Arc<dyn Material>; 2] =
+            [Dielectric::new(1.5), Metal::new(Vec3::new(1., 1., 1.), 0.)];
+        for material in materials {
+            assert!(material.is_delta());
+            let sample = material.sample(&mut rng, &ray_in, &rec).unwrap();
+            assert!(sample.delta);
+            assert_eq!(material.pdf(&ray_in, &rec, sample.direction), 0.);
+            assert_eq!(
+                material.eval(&ray_in, &rec, sample.direction),
+                Vec3::default()
+            );
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::{hittable::HitRecord, random::Random, vec3::Vec3, Ray};
 use std::sync::Arc;
 
-use crate::Material;
+use crate::{
+    material::{BsdfSample, Lobe},
+    Material,
+};
 
 #[derive(Clone, Copy)]
 pub struct Dielectric {
@@ -9,15 +12,7 @@ pub struct Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        *attenuation = Vec3::new(1., 1., 1.);
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -29,17 +24,45 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction: Vec3 = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.random(None, None)
-        {
-            Vec3::reflect(unit_direction, rec.normal)
+        let reflectance = if cannot_refract {
+            1.
         } else {
-            Vec3::refract(unit_direction, rec.normal, refraction_ratio)
+            Dielectric::reflectance(cos_theta, refraction_ratio)
         };
-
-        *scattered = Ray::new(rec.p, direction, ray_in.time);
+        // Reflection and refraction are picked in proportion to how much light they carry.
+        let (direction, probability, lobe) = if reflectance > rng.random(None, None) {
+            (
+                Vec3::reflect(unit_direction, rec.normal),
+                reflectance,
+                Lobe::Glossy,
+            )
+        } else {
+            (
+                Vec3::refract(unit_direction, rec.normal, refraction_ratio),
+                1. - reflectance,
+                Lobe::Transmission,
+            )
+        };
+        let direction = Vec3::unit_vector(direction);
+        let cosine = self.cosine(rec, direction);
+        if cosine == 0. {
+            return None;
+        }
+        Some(BsdfSample {
+            direction,
+            f: Vec3::new(1., 1., 1.) * probability / cosine,
+            pdf: probability,
+            delta: true,
+            lobe,
+        })
+    }
+    fn is_delta(&self) -> bool {
         true
     }
+    /// Clear glass lets all light through, one way or the other.
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        Vec3::new(1., 1., 1.)
+    }
 }
 
 impl Dielectric {
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::{hittable::HitRecord, random::Random, ray::Ray, texture::Texture, vec3::Vec3};
 
-use crate::Material;
+use crate::{material::BsdfSample, Material};
 
 pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
@@ -15,17 +15,21 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn scatter(
+    fn sample(
         &self,
         _rng: &mut Random<f64>,
         _ray_in: &Ray,
         _rec: &HitRecord,
-        _attenuation: &mut Vec3,
-        _scattered: &mut Ray,
-    ) -> bool {
-        false
+    ) -> Option<BsdfSample> {
+        None
     }
     fn emitted(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
         self.emit.color(_u, _v, _p)
     }
+    fn is_emissive(&self) -> bool {
+        true
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.emit.value(rec)
+    }
 }
@@ -1,8 +1,11 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{hittable::HitRecord, random::Random, ray::Ray, texture::Texture, vec3::Vec3};
 
-use crate::Material;
+use crate::{
+    material::{BsdfSample, Lobe},
+    Material,
+};
 
 pub struct Isotropic {
     pub albedo: Arc<dyn Texture>,
@@ -14,16 +17,26 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
-        *scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(rng), ray_in.time);
-        *attenuation = self.albedo.color(rec.u, rec.v, rec.p);
-        true
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample> {
+        let direction = Vec3::random_unit_vector(rng);
+        Some(BsdfSample {
+            direction,
+            f: self.eval(ray_in, rec, direction),
+            pdf: self.pdf(ray_in, rec, direction),
+            delta: false,
+            lobe: Lobe::Volume,
+        })
+    }
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, _direction: Vec3) -> Vec3 {
+        self.albedo.value(rec) / (4. * PI)
+    }
+    fn pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f64 {
+        1. / (4. * PI)
+    }
+    fn cosine(&self, _rec: &HitRecord, _direction: Vec3) -> f64 {
+        1.
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.albedo.value(rec)
     }
 }
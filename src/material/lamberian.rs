@@ -1,8 +1,11 @@
 use crate::{hittable::HitRecord, random::Random, texture::Texture, vec3::Vec3, Ray};
 
-use crate::Material;
+use crate::{
+    material::{BsdfSample, Lobe},
+    Material,
+};
 
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 #[derive(Clone)]
 pub struct Lamberian {
@@ -10,21 +13,33 @@ pub struct Lamberian {
 }
 
 impl Material for Lamberian {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample> {
+        // The normal's tip plus a uniform unit vector is distributed by the cosine.
         let mut scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
         if Vec3::near_zero(scatter_direction) {
             scatter_direction = rec.normal;
         }
-        *scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
-        *attenuation = self.albedo.color(rec.u, rec.v, rec.p);
-        true
+        let direction = Vec3::unit_vector(scatter_direction);
+        Some(BsdfSample {
+            direction,
+            f: self.eval(ray_in, rec, direction),
+            pdf: self.pdf(ray_in, rec, direction),
+            delta: false,
+            lobe: Lobe::Diffuse,
+        })
+    }
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        if Vec3::dot(direction, rec.normal) > 0. {
+            self.albedo.value(rec) / PI
+        } else {
+            Vec3::default()
+        }
+    }
+    fn pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        (Vec3::dot(Vec3::unit_vector(direction), rec.normal) / PI).max(0.)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        self.albedo.value(rec)
     }
 }
 
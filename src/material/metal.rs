@@ -1,7 +1,10 @@
 use crate::{hittable::HitRecord, random::Random, vec3::Vec3, Ray};
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::Material;
+use crate::{
+    material::{BsdfSample, Lobe},
+    Material,
+};
 
 #[derive(Clone, Copy)]
 pub struct Metal {
@@ -10,22 +13,65 @@ pub struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        rng: &mut Random<f64>,
-        ray_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Vec3,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn sample(&self, rng: &mut Random<f64>, ray_in: &Ray, rec: &HitRecord) -> Option<BsdfSample> {
         let reflected = Vec3::reflect(Vec3::unit_vector(ray_in.direction), rec.normal);
-        *scattered = Ray::new(
-            rec.p,
-            reflected + Vec3::random_in_unit_sphere(rng) * self.fuzz,
-            ray_in.time,
-        );
-        *attenuation = self.albedo;
-        Vec3::dot(scattered.direction, rec.normal) > 0.
+        let direction = Vec3::unit_vector(reflected + Vec3::random_in_unit_sphere(rng) * self.fuzz);
+        // Fuzz that points below the surface absorbs the ray.
+        let cosine = Vec3::dot(direction, rec.normal);
+        if cosine <= 0. {
+            return None;
+        }
+        if self.is_delta() {
+            return Some(BsdfSample {
+                direction,
+                f: self.albedo / cosine,
+                pdf: 1.,
+                delta: true,
+                lobe: Lobe::Glossy,
+            });
+        }
+        Some(BsdfSample {
+            direction,
+            f: self.eval(ray_in, rec, direction),
+            pdf: self.pdf(ray_in, rec, direction),
+            delta: false,
+            lobe: Lobe::Glossy,
+        })
+    }
+    /// Chosen so that every sample is weighted by the albedo, like a perfect mirror.
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        let cosine = Vec3::dot(Vec3::unit_vector(direction), rec.normal);
+        if cosine <= 0. {
+            return Vec3::default();
+        }
+        self.albedo * self.pdf(ray_in, rec, direction) / cosine
+    }
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> f64 {
+        let direction = Vec3::unit_vector(direction);
+        if self.is_delta() || Vec3::dot(direction, rec.normal) <= 0. {
+            return 0.;
+        }
+        let reflected = Vec3::reflect(Vec3::unit_vector(ray_in.direction), rec.normal);
+        // Directions are the mirror direction plus a uniform point in a ball of radius
+        // `fuzz`, so the density integrates `t²` over the part of the ray `t * direction`
+        // inside that ball.
+        let c = Vec3::dot(direction, reflected);
+        let discriminant = c * c - 1. + self.fuzz * self.fuzz;
+        if discriminant <= 0. {
+            return 0.;
+        }
+        let t1 = c + discriminant.sqrt();
+        let t0 = (c - discriminant.sqrt()).max(0.);
+        if t1 <= 0. {
+            return 0.;
+        }
+        (t1.powi(3) - t0.powi(3)) / (4. * PI * self.fuzz.powi(3))
+    }
+    fn is_delta(&self) -> bool {
+        self.fuzz == 0.
+    }
+    fn albedo(&self, _rec: &HitRecord) -> Vec3 {
+        self.albedo
     }
 }
 
@@ -33,7 +79,7 @@ impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Arc<Self> {
         Arc::from(Self {
             albedo,
-            fuzz: if fuzz < 1. { fuzz } else { 1. },
+            fuzz: fuzz.clamp(0., 1.),
         })
     }
 }
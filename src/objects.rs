@@ -1,8 +1,21 @@
-use crate::{aabb::Aabb, hittable::HitRecord, ray::Ray};
+use std::sync::Arc;
+
+use crate::{aabb::Aabb, hittable::HitRecord, random::Random, ray::Ray, vec3::Vec3};
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut Aabb) -> bool;
+    /// Density, per unit solid angle seen from `origin`, with which `random` picks
+    /// `direction`; zero for objects that cannot be sampled.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _rng: &mut Random<f64>) -> f64 {
+        0.
+    }
+    /// Random direction from `origin` towards a point of the object.
+    fn random(&self, _origin: Vec3, _rng: &mut Random<f64>) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
+    /// Adds the emissive objects that can be sampled with `random` to `lights`.
+    fn collect_lights(self: Arc<Self>, _lights: &mut Vec<Arc<dyn Hittable>>) {}
 }
 
 pub mod box_render;
@@ -11,4 +24,6 @@ pub mod moving_sphere;
 pub mod rect;
 pub mod sphere;
 pub mod translate;
+pub mod triangle;
+pub mod triangle_mesh;
 pub mod y_rotation;
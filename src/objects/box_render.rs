@@ -4,11 +4,12 @@ use crate::{
     aabb::Aabb,
     hittable::{HitRecord, HittableList},
     material::Material,
+    random::Random,
     vec3::Vec3,
 };
 
 use crate::{
-    objects::rect::{xy_rect, xz_rect, yz_rect},
+    objects::rect::{XyRect, XzRect, YzRect},
     Hittable,
 };
 
@@ -21,7 +22,7 @@ pub struct BoxRender {
 impl BoxRender {
     pub fn new(p0: Vec3, p1: Vec3, material: Arc<dyn Material>) -> Arc<Self> {
         let mut sides = HittableList::new(vec![]);
-        sides.objects.push(xy_rect::new(
+        sides.objects.push(XyRect::new(
             p0.x_r,
             p1.x_r,
             p0.y_g,
@@ -29,7 +30,7 @@ impl BoxRender {
             p1.z_b,
             material.clone(),
         ));
-        sides.objects.push(xy_rect::new(
+        sides.objects.push(XyRect::new(
             p0.x_r,
             p1.x_r,
             p0.y_g,
@@ -37,7 +38,7 @@ impl BoxRender {
             p0.z_b,
             material.clone(),
         ));
-        sides.objects.push(xz_rect::new(
+        sides.objects.push(XzRect::new(
             p0.x_r,
             p1.x_r,
             p0.z_b,
@@ -45,7 +46,7 @@ impl BoxRender {
             p1.y_g,
             material.clone(),
         ));
-        sides.objects.push(xz_rect::new(
+        sides.objects.push(XzRect::new(
             p0.x_r,
             p1.x_r,
             p0.z_b,
@@ -53,7 +54,7 @@ impl BoxRender {
             p0.y_g,
             material.clone(),
         ));
-        sides.objects.push(yz_rect::new(
+        sides.objects.push(YzRect::new(
             p0.y_g,
             p1.y_g,
             p0.z_b,
@@ -61,7 +62,7 @@ impl BoxRender {
             p1.x_r,
             material.clone(),
         ));
-        sides.objects.push(yz_rect::new(
+        sides.objects.push(YzRect::new(
             p0.y_g,
             p1.y_g,
             p0.z_b,
@@ -78,11 +79,22 @@ impl BoxRender {
 }
 
 impl Hittable for BoxRender {
-    fn hit(&self, ray: &crate::ray::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        self.sides.hit(ray, t_min, t_max)
+    fn hit(
+        &self,
+        ray: &crate::ray::Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut Random<f64>,
+    ) -> Option<HitRecord> {
+        self.sides.hit(ray, t_min, t_max, rng)
     }
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
         *output_box = Aabb::new(self.pmin, self.pmax);
         true
     }
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        for side in &self.sides.objects {
+            side.clone().collect_lights(lights);
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{hittable::HitRecord, material::Material, ray::Ray, vec3::Vec3};
+use crate::{hittable::HitRecord, material::Material, random::Random, ray::Ray, vec3::Vec3};
 
 use crate::Hittable;
 
@@ -21,9 +21,9 @@ impl<H: Hittable + ?Sized> ConstantMedium<H> {
 }
 
 impl<H: Hittable + ?Sized> Hittable for ConstantMedium<H> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if let Some(mut rec1) = self.boundary.hit(ray, f64::MIN, f64::MAX) {
-            if let Some(mut rec2) = self.boundary.hit(ray, rec1.t + 0.0001, f64::MAX) {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
+        if let Some(mut rec1) = self.boundary.hit(ray, f64::MIN, f64::MAX, rng) {
+            if let Some(mut rec2) = self.boundary.hit(ray, rec1.t + 0.0001, f64::MAX, rng) {
                 if rec1.t < t_min {
                     rec1.t = t_min;
                 }
@@ -38,7 +38,7 @@ impl<H: Hittable + ?Sized> Hittable for ConstantMedium<H> {
                 }
                 let ray_length = Vec3::length(ray.direction);
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * rand::random::<f64>().ln();
+                let hit_distance = self.neg_inv_density * rng.random(None, None).ln();
                 if hit_distance > distance_inside_boundary {
                     return None;
                 }
@@ -1,4 +1,6 @@
-use crate::{aabb::Aabb, hittable::HitRecord, material::Material, ray::Ray, vec3::Vec3};
+use crate::{
+    aabb::Aabb, hittable::HitRecord, material::Material, random::Random, ray::Ray, vec3::Vec3,
+};
 
 use crate::Hittable;
 use std::f64::consts;
@@ -17,7 +19,7 @@ pub struct MovingSphere {
 }
 
 impl Hittable for MovingSphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let oc = ray.origin - self.center(ray.time);
         let a: f64 = Vec3::length_squared(ray.direction);
         let half_b: f64 = Vec3::dot(oc, ray.direction);
@@ -1,8 +1,21 @@
-use crate::{hittable::HitRecord, objects::Aabb, ray::Ray, vec3::Vec3, Hittable, Material};
+use crate::{
+    hittable::HitRecord, objects::Aabb, random::Random, ray::Ray, vec3::Vec3, Hittable, Material,
+};
 
 use std::sync::Arc;
 
-pub struct xy_rect {
+/// Density of picking a point uniformly on a rectangle of `area`, per unit solid angle
+/// around `direction` as seen from the ray's origin, given where the ray hits it.
+fn area_pdf(rec: &HitRecord, direction: Vec3, area: f64) -> f64 {
+    let distance_squared = rec.t.powi(2) * Vec3::length_squared(direction);
+    let cosine = Vec3::dot(direction, rec.normal).abs() / Vec3::length(direction);
+    if cosine == 0. {
+        return 0.;
+    }
+    distance_squared / (cosine * area)
+}
+
+pub struct XyRect {
     pub mp: Arc<dyn Material>,
     pub x0: f64,
     pub x1: f64,
@@ -11,7 +24,7 @@ pub struct xy_rect {
     pub k: f64,
 }
 
-impl xy_rect {
+impl XyRect {
     pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mp: Arc<dyn Material>) -> Arc<Self> {
         Arc::from(Self {
             mp,
@@ -24,7 +37,7 @@ impl xy_rect {
     }
 }
 
-impl Hittable for xy_rect {
+impl Hittable for XyRect {
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
         *output_box = Aabb::new(
             Vec3::new(self.x0, self.y0, self.k - 0.0001),
@@ -32,7 +45,7 @@ impl Hittable for xy_rect {
         );
         true
     }
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let t = (self.k - ray.origin.z_b) / ray.direction.z_b;
         if t < t_min || t > t_max {
             return None;
@@ -56,8 +69,27 @@ impl Hittable for xy_rect {
 
         Some(rec)
     }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let ray = Ray::new(origin, direction, 0.);
+        match self.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) => area_pdf(&rec, direction, (self.x1 - self.x0) * (self.y1 - self.y0)),
+            None => 0.,
+        }
+    }
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        Vec3::new(
+            rng.random(Some(self.x0), Some(self.x1)),
+            rng.random(Some(self.y0), Some(self.y1)),
+            self.k,
+        ) - origin
+    }
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mp.is_emissive() {
+            lights.push(self);
+        }
+    }
 }
-pub struct xz_rect {
+pub struct XzRect {
     pub mp: Arc<dyn Material>,
     pub x0: f64,
     pub x1: f64,
@@ -66,7 +98,7 @@ pub struct xz_rect {
     pub k: f64,
 }
 
-impl xz_rect {
+impl XzRect {
     pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mp: Arc<dyn Material>) -> Arc<Self> {
         Arc::from(Self {
             mp,
@@ -79,8 +111,8 @@ impl xz_rect {
     }
 }
 
-impl Hittable for xz_rect {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Hittable for XzRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let t = (self.k - ray.origin.y_g) / ray.direction.y_g;
         if t < t_min || t > t_max {
             return None;
@@ -109,8 +141,27 @@ impl Hittable for xz_rect {
         );
         true
     }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let ray = Ray::new(origin, direction, 0.);
+        match self.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) => area_pdf(&rec, direction, (self.x1 - self.x0) * (self.z1 - self.z0)),
+            None => 0.,
+        }
+    }
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        Vec3::new(
+            rng.random(Some(self.x0), Some(self.x1)),
+            self.k,
+            rng.random(Some(self.z0), Some(self.z1)),
+        ) - origin
+    }
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mp.is_emissive() {
+            lights.push(self);
+        }
+    }
 }
-pub struct yz_rect {
+pub struct YzRect {
     pub mp: Arc<dyn Material>,
     pub y0: f64,
     pub y1: f64,
@@ -119,7 +170,7 @@ pub struct yz_rect {
     pub k: f64,
 }
 
-impl yz_rect {
+impl YzRect {
     pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mp: Arc<dyn Material>) -> Arc<Self> {
         Arc::from(Self {
             mp,
@@ -131,8 +182,8 @@ impl yz_rect {
         })
     }
 }
-impl Hittable for yz_rect {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Hittable for YzRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let t = (self.k - ray.origin.x_r) / ray.direction.x_r;
         if t < t_min || t > t_max {
             return None;
@@ -162,4 +213,23 @@ impl Hittable for yz_rect {
         );
         true
     }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let ray = Ray::new(origin, direction, 0.);
+        match self.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) => area_pdf(&rec, direction, (self.y1 - self.y0) * (self.z1 - self.z0)),
+            None => 0.,
+        }
+    }
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        Vec3::new(
+            self.k,
+            rng.random(Some(self.y0), Some(self.y1)),
+            rng.random(Some(self.z0), Some(self.z1)),
+        ) - origin
+    }
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.mp.is_emissive() {
+            lights.push(self);
+        }
+    }
 }
@@ -1,5 +1,7 @@
-use crate::{aabb::Aabb, hittable::HitRecord, material::Material, ray::Ray};
-use std::f64::consts;
+use crate::{
+    aabb::Aabb, hittable::HitRecord, material::Material, onb::Onb, random::Random, ray::Ray,
+};
+use std::f64::consts::{self, PI};
 
 use std::sync::Arc;
 
@@ -13,7 +15,7 @@ pub struct Sphere {
     pub material: Arc<dyn Material>,
 }
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
         let a: f64 = Vec3::length_squared(ray.direction);
         let oc = ray.origin - self.center;
         let half_b: f64 = Vec3::dot(oc, ray.direction);
@@ -53,6 +55,44 @@ impl Hittable for Sphere {
         );
         true
     }
+
+    /// Uniform over the cone of directions in which the sphere is seen from `origin`.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let ray = Ray::new(origin, direction, 0.);
+        if self.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng).is_none() {
+            return 0.;
+        }
+        let distance_squared = Vec3::length_squared(self.center - origin);
+        if distance_squared <= self.radius.powi(2) {
+            return 1. / (4. * PI);
+        }
+        1. / (2. * PI * Self::one_minus_cos_theta_max(self.radius, distance_squared))
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = Vec3::length_squared(direction);
+        if distance_squared <= self.radius.powi(2) {
+            return Vec3::random_unit_vector(rng);
+        }
+        let one_minus_cos_theta_max = Self::one_minus_cos_theta_max(self.radius, distance_squared);
+        let r1 = rng.random(None, None);
+        let r2 = rng.random(None, None);
+        let z = 1. - r2 * one_minus_cos_theta_max;
+        let phi = 2. * PI * r1;
+        let sin_theta = (1. - z * z).max(0.).sqrt();
+        Onb::build_from_w(direction).local(Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            z,
+        ))
+    }
+
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
 }
 impl Sphere {
     pub fn new(center: Vec3, radius: f64, material: Arc<dyn Material>) -> Arc<Self> {
@@ -62,6 +102,12 @@ impl Sphere {
             material,
         })
     }
+    /// `1 - cos θmax` of the cone the sphere fills, written so that it stays accurate
+    /// for small, distant spheres.
+    fn one_minus_cos_theta_max(radius: f64, distance_squared: f64) -> f64 {
+        let sin_squared = radius.powi(2) / distance_squared;
+        sin_squared / (1. + (1. - sin_squared).sqrt())
+    }
     pub fn get_sphere_uv(p: Vec3, u: &mut f64, v: &mut f64) {
         *u = 0.5 + f64::atan2(-p.z_b, p.x_r) * 0.5 * consts::FRAC_1_PI;
         *v = 0.5 + f64::asin(p.y_g) * consts::FRAC_1_PI;
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{aabb::Aabb, hittable::HitRecord, ray::Ray, vec3::Vec3};
+use crate::{aabb::Aabb, hittable::HitRecord, random::Random, ray::Ray, vec3::Vec3};
 
 use crate::Hittable;
 
@@ -15,9 +15,9 @@ impl<H: Hittable + ?Sized> Translate<H> {
 }
 
 impl<H: Hittable + ?Sized> Hittable for Translate<H> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
         let moved = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
-        if let Some(mut rec) = self.hittable.hit(&moved, t_min, t_max) {
+        if let Some(mut rec) = self.hittable.hit(&moved, t_min, t_max, rng) {
             rec.p += self.offset;
             Some(rec)
         } else {
@@ -32,4 +32,21 @@ impl<H: Hittable + ?Sized> Hittable for Translate<H> {
             false
         }
     }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        self.hittable
+            .pdf_value(origin - self.offset, direction, rng)
+    }
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        self.hittable.random(origin - self.offset, rng)
+    }
+    /// Moves every light found inside by the same offset.
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = vec![];
+        self.hittable.clone().collect_lights(&mut inner);
+        lights.extend(
+            inner
+                .into_iter()
+                .map(|light| Translate::new(light, self.offset) as Arc<dyn Hittable>),
+        );
+    }
 }
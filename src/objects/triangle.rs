@@ -0,0 +1,310 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb, hittable::HitRecord, material::Material, random::Random, ray::Ray, vec3::Vec3,
+};
+
+use crate::Hittable;
+
+/// A ray prepared for the watertight ray/triangle test of Woop, Benthin and Wald (2013).
+///
+/// The ray is sheared so that it points along +z; edges shared by two triangles then
+/// evaluate to exactly the same value on both sides, so rays cannot slip through them.
+pub struct WatertightRay {
+    origin: Vec3,
+    kx: usize,
+    ky: usize,
+    kz: usize,
+    sx: f64,
+    sy: f64,
+    sz: f64,
+}
+
+impl WatertightRay {
+    pub fn new(ray: &Ray) -> Self {
+        let d = ray.direction;
+        let abs = Vec3::new(d.x_r.abs(), d.y_g.abs(), d.z_b.abs());
+        let kz = if abs.x_r > abs.y_g {
+            if abs.x_r > abs.z_b {
+                0
+            } else {
+                2
+            }
+        } else if abs.y_g > abs.z_b {
+            1
+        } else {
+            2
+        };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if d[kz] < 0. {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+        Self {
+            origin: ray.origin,
+            kx,
+            ky,
+            kz,
+            sx: d[kx] / d[kz],
+            sy: d[ky] / d[kz],
+            sz: 1. / d[kz],
+        }
+    }
+
+    /// Returns the ray parameter and the barycentric weights of `p0`, `p1` and `p2`.
+    #[inline]
+    pub fn intersect(
+        &self,
+        p0: Vec3,
+        p1: Vec3,
+        p2: Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, f64, f64, f64)> {
+        let a = p0 - self.origin;
+        let b = p1 - self.origin;
+        let c = p2 - self.origin;
+
+        let ax = a[self.kx] - self.sx * a[self.kz];
+        let ay = a[self.ky] - self.sy * a[self.kz];
+        let bx = b[self.kx] - self.sx * b[self.kz];
+        let by = b[self.ky] - self.sy * b[self.kz];
+        let cx = c[self.kx] - self.sx * c[self.kz];
+        let cy = c[self.ky] - self.sy * c[self.kz];
+
+        let u = cx * by - cy * bx;
+        let v = ax * cy - ay * cx;
+        let w = bx * ay - by * ax;
+        // A ray right on an edge counts as nudged off it by an infinitesimal (δ, δ²),
+        // so that it hits just one of the two triangles sharing the edge.
+        let side = |e: f64, (px, py): (f64, f64), (qx, qy): (f64, f64)| {
+            if e != 0. {
+                e
+            } else if py != qy {
+                py - qy
+            } else {
+                qx - px
+            }
+        };
+        let (su, sv, sw) = if u == 0. || v == 0. || w == 0. {
+            (
+                side(u, (cx, cy), (bx, by)),
+                side(v, (ax, ay), (cx, cy)),
+                side(w, (bx, by), (ax, ay)),
+            )
+        } else {
+            (u, v, w)
+        };
+        if (su < 0. || sv < 0. || sw < 0.) && (su > 0. || sv > 0. || sw > 0.) {
+            return None;
+        }
+        let det = u + v + w;
+        if det == 0. {
+            return None;
+        }
+
+        let az = self.sz * a[self.kz];
+        let bz = self.sz * b[self.kz];
+        let cz = self.sz * c[self.kz];
+        let t = (u * az + v * bz + w * cz) / det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        Some((t, u / det, v / det, w / det))
+    }
+}
+
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, material: Arc<dyn Material>) -> Arc<Self> {
+        Arc::from(Self {
+            vertices: [p0, p1, p2],
+            material,
+        })
+    }
+
+    /// Counter-clockwise winding (seen from the front) gives the outward normal.
+    pub fn geometric_normal(p0: Vec3, p1: Vec3, p2: Vec3) -> Vec3 {
+        Vec3::unit_vector(Vec3::cross(p1 - p0, p2 - p0))
+    }
+
+    /// Point drawn uniformly from the triangle.
+    pub fn random_point(p0: Vec3, p1: Vec3, p2: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        let root = rng.random(None, None).sqrt();
+        let b1 = rng.random(None, None) * root;
+        p0 * (1. - root) + p1 * b1 + p2 * (root - b1)
+    }
+
+    pub fn bounding_box_of(p0: Vec3, p1: Vec3, p2: Vec3) -> Aabb {
+        let mut bbox = Aabb::new(p0, p0);
+        for p in [p1, p2] {
+            bbox = Aabb::surrounding_box(bbox, Aabb::new(p, p));
+        }
+        // Pad so that axis-aligned triangles still have a non-empty box.
+        let padding = Vec3::new(0.0001, 0.0001, 0.0001);
+        Aabb::new(bbox.min - padding, bbox.max + padding)
+    }
+
+    /// Fills the hit record with the geometric normal deciding the face side
+    /// and the (optional) shading normal oriented to match it.
+    pub fn set_normals(rec: &mut HitRecord, ray: &Ray, geometric: Vec3, shading: Option<Vec3>) {
+        rec.front_face = Vec3::dot(ray.direction, geometric) < 0.0;
+        let shading = match shading {
+            Some(n) if !Vec3::near_zero(n) => Vec3::unit_vector(n),
+            _ => geometric,
+        };
+        let towards_front = if rec.front_face { 1. } else { -1. };
+        rec.normal = if Vec3::dot(shading, geometric) * towards_front >= 0. {
+            shading
+        } else {
+            -shading
+        };
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _rng: &mut Random<f64>) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.vertices;
+        let (t, b0, b1, b2) = WatertightRay::new(ray).intersect(p0, p1, p2, t_min, t_max)?;
+        let mut rec = HitRecord {
+            t,
+            p: p0 * b0 + p1 * b1 + p2 * b2,
+            material: Some(self.material.clone()),
+            u: b1,
+            v: b2,
+            ..Default::default()
+        };
+        Triangle::set_normals(&mut rec, ray, Triangle::geometric_normal(p0, p1, p2), None);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        let [p0, p1, p2] = self.vertices;
+        *output_box = Triangle::bounding_box_of(p0, p1, p2);
+        true
+    }
+
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let [p0, p1, p2] = self.vertices;
+        let area = Vec3::length(Vec3::cross(p1 - p0, p2 - p0)) / 2.;
+        let ray = Ray::new(origin, direction, 0.);
+        match self.hit(&ray, f64::MIN_POSITIVE, f64::MAX, rng) {
+            Some(rec) if area > 0. => {
+                let distance_squared = rec.t.powi(2) * Vec3::length_squared(direction);
+                let cosine = Vec3::dot(direction, rec.normal).abs() / Vec3::length(direction);
+                if cosine == 0. {
+                    0.
+                } else {
+                    distance_squared / (cosine * area)
+                }
+            }
+            _ => 0.,
+        }
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        let [p0, p1, p2] = self.vertices;
+        Triangle::random_point(p0, p1, p2, rng) - origin
+    }
+
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.material.is_emissive() {
+            lights.push(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        material::lamberian::Lamberian, random::SampleRng, texture::solid_color::SolidColor,
+    };
+
+    /// The square [-1, 1]² at z = 0 as four triangles around its center, all facing +z.
+    fn fan() -> Vec<[Vec3; 3]> {
+        let center = Vec3::new(0., 0., 0.);
+        let corners = [
+            Vec3::new(-1., -1., 0.),
+            Vec3::new(1., -1., 0.),
+            Vec3::new(1., 1., 0.),
+            Vec3::new(-1., 1., 0.),
+        ];
+        (0..4)
+            .map(|i| [center, corners[i], corners[(i + 1) % 4]])
+            .collect()
+    }
+
+    fn hits(fan: &[[Vec3; 3]], ray: &Ray) -> usize {
+        let watertight = WatertightRay::new(ray);
+        fan.iter()
+            .filter(|[p0, p1, p2]| {
+                watertight
+                    .intersect(*p0, *p1, *p2, f64::MIN_POSITIVE, f64::MAX)
+                    .is_some()
+            })
+            .count()
+    }
+
+    #[test]
+    fn shared_edges_and_vertices_are_hit_once() {
+        let fan = fan();
+        let mut rng = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        let mut targets = vec![Vec3::new(0., 0., 0.)];
+        for s in [-0.75, -0.5, -0.125, 0.25, 0.5, 0.875] {
+            targets.push(Vec3::new(s, s, 0.));
+            targets.push(Vec3::new(s, -s, 0.));
+        }
+        for target in targets {
+            // Straight on from either side, where the edges are hit exactly, and at
+            // random angles.
+            let mut directions = vec![Vec3::new(0., 0., -1.), Vec3::new(0., 0., 1.)];
+            for _ in 0..200 {
+                let mut direction = Vec3::random_unit_vector(&mut rng);
+                if direction.z_b.abs() < 0.1 {
+                    direction.z_b = 0.1;
+                }
+                directions.push(direction);
+            }
+            for direction in directions {
+                let ray = Ray::new(target - direction * 3., direction, 0.);
+                assert_eq!(
+                    hits(&fan, &ray),
+                    1,
+                    "ray through {:?} along {:?}",
+                    target,
+                    direction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hits_report_barycentric_coordinates_and_the_facing_side() {
+        let material = Lamberian::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)));
+        let triangle = Triangle::new(
+            Vec3::new(0., 0., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 4., 0.),
+            material,
+        );
+        let mut rng = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        for (z, front_face) in [(3., true), (-3., false)] {
+            let ray = Ray::new(Vec3::new(0.5, 1., z), Vec3::new(0., 0., -z), 0.);
+            let rec = triangle
+                .hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut rng)
+                .unwrap();
+            assert!((rec.t - 1.).abs() < 1e-12);
+            assert!(Vec3::length(rec.p - Vec3::new(0.5, 1., 0.)) < 1e-12);
+            assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+            assert_eq!(rec.front_face, front_face);
+            assert!(Vec3::length(rec.normal - Vec3::new(0., 0., z.signum())) < 1e-12);
+        }
+    }
+}
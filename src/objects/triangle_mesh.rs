@@ -0,0 +1,490 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    bvh_node,
+    hittable::HitRecord,
+    material::Material,
+    objects::triangle::{Triangle, WatertightRay},
+    random::Random,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+use crate::Hittable;
+
+const MAX_FACES_PER_LEAF: usize = 4;
+
+/// Node of the flattened BVH over the faces of a mesh.
+///
+/// A leaf (`count > 0`) covers `faces[start..start + count]`. An inner node's left
+/// child directly follows it in the node array and its right child is at `start`.
+#[derive(Clone, Copy, Default)]
+struct MeshBvhNode {
+    bbox: Aabb,
+    start: u32,
+    count: u32,
+}
+
+/// An indexed triangle mesh sharing one set of vertex buffers between all faces.
+///
+/// `normals`, `uvs` and `colors`, when present, hold one entry per vertex and are
+/// interpolated across each face. Faces are kept in a BVH of their own, so a mesh is a single
+/// `Hittable` no matter how many triangles it has.
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f64, f64)>>,
+    pub colors: Option<Vec<Vec3>>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material>,
+    nodes: Vec<MeshBvhNode>,
+    faces: Vec<u32>,
+    /// Total area of the faces up to and including each one, for sampling points on
+    /// emissive meshes.
+    area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        colors: Option<Vec<Vec3>>,
+        indices: Vec<[u32; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        let mut mesh = Self {
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            material,
+            nodes: vec![],
+            faces: vec![],
+            area_cdf: vec![],
+        };
+        mesh.build_bvh();
+        let mut area = 0.;
+        mesh.area_cdf = (0..mesh.indices.len() as u32)
+            .map(|face| {
+                let (p0, p1, p2) = mesh.face_vertices(face);
+                area += Vec3::length(Vec3::cross(p1 - p0, p2 - p0)) / 2.;
+                area
+            })
+            .collect();
+        Arc::from(mesh)
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn face_vertices(&self, face: u32) -> (Vec3, Vec3, Vec3) {
+        let [i0, i1, i2] = self.indices[face as usize];
+        (
+            self.positions[i0 as usize],
+            self.positions[i1 as usize],
+            self.positions[i2 as usize],
+        )
+    }
+
+    fn build_bvh(&mut self) {
+        if self.indices.is_empty() {
+            return;
+        }
+        let (boxes, centroids): (Vec<Aabb>, Vec<Vec3>) = (0..self.indices.len() as u32)
+            .map(|face| {
+                let (p0, p1, p2) = self.face_vertices(face);
+                (Triangle::bounding_box_of(p0, p1, p2), (p0 + p1 + p2) / 3.)
+            })
+            .unzip();
+        self.faces = (0..self.indices.len() as u32).collect();
+        self.nodes = Vec::with_capacity(2 * self.indices.len() / MAX_FACES_PER_LEAF + 1);
+        let face_count = self.faces.len();
+        Self::build_node(
+            &mut self.nodes,
+            &mut self.faces,
+            0,
+            face_count,
+            &boxes,
+            &centroids,
+        );
+    }
+
+    fn build_node(
+        nodes: &mut Vec<MeshBvhNode>,
+        faces: &mut [u32],
+        start: usize,
+        end: usize,
+        boxes: &[Aabb],
+        centroids: &[Vec3],
+    ) {
+        let range = &mut faces[start..end];
+        let mut bbox = boxes[range[0] as usize];
+        let mut centroid_min = centroids[range[0] as usize];
+        let mut centroid_max = centroid_min;
+        for &face in range.iter() {
+            bbox = Aabb::surrounding_box(bbox, boxes[face as usize]);
+            let c = centroids[face as usize];
+            for axis in 0..3 {
+                centroid_min[axis] = centroid_min[axis].min(c[axis]);
+                centroid_max[axis] = centroid_max[axis].max(c[axis]);
+            }
+        }
+        let index = nodes.len();
+        nodes.push(MeshBvhNode {
+            bbox,
+            start: start as u32,
+            count: (end - start) as u32,
+        });
+        if end - start <= MAX_FACES_PER_LEAF {
+            return;
+        }
+
+        // Median split along the axis with the widest spread of centroids.
+        let extent = centroid_max - centroid_min;
+        let axis = if extent.x_r > extent.y_g && extent.x_r > extent.z_b {
+            0
+        } else if extent.y_g > extent.z_b {
+            1
+        } else {
+            2
+        };
+        let mid = (end - start) / 2;
+        range.select_nth_unstable_by(mid, |a, b| {
+            centroids[*a as usize][axis]
+                .partial_cmp(&centroids[*b as usize][axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        Self::build_node(nodes, faces, start, start + mid, boxes, centroids);
+        let right = nodes.len();
+        Self::build_node(nodes, faces, start + mid, end, boxes, centroids);
+        nodes[index].start = right as u32;
+        nodes[index].count = 0;
+    }
+
+    fn face_hit(&self, face: u32, hit: (f64, f64, f64, f64), ray: &Ray) -> HitRecord {
+        let (t, b0, b1, b2) = hit;
+        let [i0, i1, i2] = self.indices[face as usize];
+        let (i0, i1, i2) = (i0 as usize, i1 as usize, i2 as usize);
+        let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+        let (u, v) = match &self.uvs {
+            Some(uvs) => (
+                uvs[i0].0 * b0 + uvs[i1].0 * b1 + uvs[i2].0 * b2,
+                uvs[i0].1 * b0 + uvs[i1].1 * b1 + uvs[i2].1 * b2,
+            ),
+            None => (b1, b2),
+        };
+        let mut rec = HitRecord {
+            t,
+            p: p0 * b0 + p1 * b1 + p2 * b2,
+            material: Some(self.material.clone()),
+            u,
+            v,
+            color: self
+                .colors
+                .as_ref()
+                .map(|colors| colors[i0] * b0 + colors[i1] * b1 + colors[i2] * b2),
+            ..Default::default()
+        };
+        let shading = self
+            .normals
+            .as_ref()
+            .map(|normals| normals[i0] * b0 + normals[i1] * b1 + normals[i2] * b2);
+        Triangle::set_normals(
+            &mut rec,
+            ray,
+            Triangle::geometric_normal(p0, p1, p2),
+            shading,
+        );
+        rec
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
+        let mut closest = None;
+        self.traverse(ray, t_min, t_max, rng, |face, hit| {
+            closest = Some((face, hit));
+            hit.0
+        });
+        closest.map(|(face, hit)| self.face_hit(face, hit, ray))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut Aabb) -> bool {
+        match self.nodes.first() {
+            Some(root) => {
+                *output_box = root.bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sums the densities of every face the ray crosses, as any of them could have
+    /// been the point `random` picked.
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        let area = self.area_cdf.last().copied().unwrap_or(0.);
+        if area == 0. {
+            return 0.;
+        }
+        let ray = Ray::new(origin, direction, 0.);
+        let length_squared = Vec3::length_squared(direction);
+        let mut pdf = 0.;
+        self.traverse(&ray, f64::MIN_POSITIVE, f64::MAX, rng, |face, (t, ..)| {
+            let (p0, p1, p2) = self.face_vertices(face);
+            let normal = Triangle::geometric_normal(p0, p1, p2);
+            let cosine = Vec3::dot(direction, normal).abs() / length_squared.sqrt();
+            if cosine > 0. {
+                pdf += t * t * length_squared / (cosine * area);
+            }
+            f64::MAX
+        });
+        pdf
+    }
+
+    /// Direction towards a point drawn uniformly from the surface of the mesh.
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        let area = self.area_cdf.last().copied().unwrap_or(0.);
+        let target = rng.random(None, None) * area;
+        let face = self
+            .area_cdf
+            .partition_point(|&cumulative| cumulative <= target)
+            .min(self.area_cdf.len() - 1);
+        let (p0, p1, p2) = self.face_vertices(face as u32);
+        Triangle::random_point(p0, p1, p2, rng) - origin
+    }
+
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        if self.material.is_emissive() && self.area_cdf.last().is_some_and(|&area| area > 0.) {
+            lights.push(self);
+        }
+    }
+}
+
+impl TriangleMesh {
+    /// Calls `visit(face, hit)` for faces that `ray` hits between `t_min` and the
+    /// distance `visit` returns, starting from `t_max`: the hit's own distance finds
+    /// the closest hit, and `f64::MAX` finds every hit.
+    fn traverse(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut Random<f64>,
+        mut visit: impl FnMut(u32, (f64, f64, f64, f64)) -> f64,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let watertight = WatertightRay::new(ray);
+        let mut closest_so_far = t_max;
+        // Median splits keep the tree balanced, so 64 levels are never exceeded.
+        let mut stack = [0usize; 64];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let index = stack[stack_len];
+            let node = self.nodes[index];
+            bvh_node::count_box_test();
+            if node.bbox.hit(ray, t_min, closest_so_far, rng).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack[stack_len] = node.start as usize;
+                stack[stack_len + 1] = index + 1;
+                stack_len += 2;
+                continue;
+            }
+            let start = node.start as usize;
+            for &face in &self.faces[start..start + node.count as usize] {
+                let (p0, p1, p2) = self.face_vertices(face);
+                if let Some(hit) = watertight.intersect(p0, p1, p2, t_min, closest_so_far) {
+                    closest_so_far = visit(face, hit).min(closest_so_far);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        material::lamberian::Lamberian, onb::Onb, random::SampleRng,
+        texture::solid_color::SolidColor,
+    };
+
+    fn random(seed: u64) -> Random<f64> {
+        Random::new(SampleRng::new(seed), Uniform::new(0., 1.))
+    }
+
+    fn material() -> Arc<dyn Material> {
+        Lamberian::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn random_point(rng: &mut Random<f64>) -> Vec3 {
+        Vec3::new(
+            rng.random(None, None),
+            rng.random(None, None),
+            rng.random(None, None),
+        )
+    }
+
+    /// Octahedron with uneven half-axes, centered at `center`.
+    fn octahedron(center: Vec3) -> Arc<TriangleMesh> {
+        let positions = vec![
+            center + Vec3::new(0.6, 0., 0.),
+            center + Vec3::new(-0.3, 0., 0.),
+            center + Vec3::new(0., 0.5, 0.),
+            center + Vec3::new(0., -0.8, 0.),
+            center + Vec3::new(0., 0., 0.4),
+            center + Vec3::new(0., 0., -0.7),
+        ];
+        let mut indices = vec![];
+        for (x, y, z) in [
+            (0, 2, 4),
+            (2, 1, 4),
+            (1, 3, 4),
+            (3, 0, 4),
+            (2, 0, 5),
+            (1, 2, 5),
+            (3, 1, 5),
+            (0, 3, 5),
+        ] {
+            indices.push([x, y, z]);
+        }
+        TriangleMesh::new(positions, None, None, None, indices, material())
+    }
+
+    #[test]
+    fn vertex_attributes_are_interpolated() {
+        let mesh = TriangleMesh::new(
+            vec![
+                Vec3::new(0., 0., 0.),
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+            ],
+            Some(vec![
+                Vec3::new(0., 0., 1.),
+                Vec3::new(1., 0., 1.),
+                Vec3::new(0., 1., 1.),
+            ]),
+            Some(vec![(0.1, 0.2), (0.9, 0.2), (0.1, 0.8)]),
+            Some(vec![
+                Vec3::new(1., 0., 0.),
+                Vec3::new(0., 1., 0.),
+                Vec3::new(0., 0., 1.),
+            ]),
+            vec![[0, 1, 2]],
+            material(),
+        );
+        // Weights 0.5, 0.25 and 0.25 of the three vertices.
+        let ray = Ray::new(Vec3::new(0.25, 0.25, 2.), Vec3::new(0., 0., -1.), 0.);
+        let rec = mesh
+            .hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut random(0))
+            .unwrap();
+        assert!(rec.front_face);
+        assert!((rec.u - 0.3).abs() < 1e-12);
+        assert!((rec.v - 0.35).abs() < 1e-12);
+        let color = rec.color.unwrap();
+        assert!(Vec3::length(color - Vec3::new(0.5, 0.25, 0.25)) < 1e-12);
+        let normal = Vec3::unit_vector(Vec3::new(0.25, 0.25, 1.));
+        assert!(Vec3::length(rec.normal - normal) < 1e-12);
+
+        // Seen from behind, the shading normal is turned around with the face.
+        let ray = Ray::new(Vec3::new(0.25, 0.25, -2.), Vec3::new(0., 0., 1.), 0.);
+        let rec = mesh
+            .hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut random(0))
+            .unwrap();
+        assert!(!rec.front_face);
+        assert!(Vec3::length(rec.normal + normal) < 1e-12);
+    }
+
+    #[test]
+    fn bvh_finds_the_same_hits_as_testing_every_face() {
+        let mut rng = random(1);
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for face in 0..300 {
+            let corner = random_point(&mut rng) * 4.;
+            for _ in 0..3 {
+                positions.push(corner + random_point(&mut rng) * 0.5);
+            }
+            indices.push([3 * face, 3 * face + 1, 3 * face + 2]);
+        }
+        let mesh = TriangleMesh::new(positions, None, None, None, indices, material());
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random_point(&mut rng) * 8. - Vec3::new(2., 2., 2.);
+            let target = random_point(&mut rng) * 4.;
+            let ray = Ray::new(origin, target - origin, 0.);
+            let watertight = WatertightRay::new(&ray);
+            let brute_force = (0..mesh.face_count() as u32)
+                .filter_map(|face| {
+                    let (p0, p1, p2) = mesh.face_vertices(face);
+                    watertight.intersect(p0, p1, p2, 0.001, f64::MAX)
+                })
+                .map(|(t, ..)| t)
+                .min_by(f64::total_cmp);
+            let found = mesh.hit(&ray, 0.001, f64::MAX, &mut rng).map(|rec| rec.t);
+            assert_eq!(found, brute_force);
+            hits += found.is_some() as u32;
+        }
+        assert!(hits > 300, "only {} rays hit", hits);
+    }
+
+    #[test]
+    fn pdf_value_is_the_density_of_random_directions() {
+        let center = Vec3::new(0.3, 1.5, -0.4);
+        let mesh = octahedron(center);
+        let origin = Vec3::default();
+        let samples = 200_000;
+
+        // Solid angle covered by the mesh, from the share of a cone around it that hits.
+        let axis = Onb::build_from_w(center - origin);
+        let cos_max = (1. - (0.8 / Vec3::length(center - origin)).powi(2)).sqrt();
+        let cone = 2. * PI * (1. - cos_max);
+        let mut rng = random(2);
+        let mut covered = 0.;
+        for _ in 0..samples {
+            let z = 1. - rng.random(None, None) * (1. - cos_max);
+            let phi = 2. * PI * rng.random(None, None);
+            let r = (1. - z * z).max(0.).sqrt();
+            let direction = axis.local(Vec3::new(r * phi.cos(), r * phi.sin(), z));
+            let ray = Ray::new(origin, direction, 0.);
+            if mesh
+                .hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut rng)
+                .is_some()
+            {
+                covered += cone;
+            }
+        }
+        covered /= samples as f64;
+
+        // Directions drawn by `random`, weighted by one over their density, measure the
+        // same solid angle if the density is right: the density integrates to one over
+        // it.
+        let mut measured = 0.;
+        for _ in 0..samples {
+            let direction = mesh.random(origin, &mut rng);
+            let pdf = mesh.pdf_value(origin, direction, &mut rng);
+            assert!(pdf > 0.);
+            measured += 1. / pdf;
+        }
+        measured /= samples as f64;
+        assert!(
+            (measured - covered).abs() < 0.02 * covered,
+            "samples measure {} sr, the mesh covers {} sr",
+            measured,
+            covered
+        );
+    }
+}
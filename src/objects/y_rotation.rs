@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use crate::hittable::HitRecord;
 
+use crate::random::Random;
 use crate::ray::Ray;
 use crate::{aabb::Aabb, vec3::Vec3};
 
@@ -17,9 +18,11 @@ pub struct YRotation<H: Hittable + ?Sized> {
 
 impl<H: Hittable + ?Sized> YRotation<H> {
     pub fn new(hittable: Arc<H>, angle: f64) -> Arc<Self> {
-        let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        let (sin_theta, cos_theta) = angle.to_radians().sin_cos();
+        Self::with_sin_cos(hittable, sin_theta, cos_theta)
+    }
+
+    fn with_sin_cos(hittable: Arc<H>, sin_theta: f64, cos_theta: f64) -> Arc<Self> {
         let mut bbox = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0));
         let has_box = hittable.bounding_box(0.0, 1.0, &mut bbox);
         let mut min = Vec3::new(f64::MAX, f64::MAX, f64::MAX);
@@ -52,24 +55,16 @@ impl<H: Hittable + ?Sized> YRotation<H> {
 }
 
 impl<H: Hittable + ?Sized> Hittable for YRotation<H> {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut origin = ray.origin;
-        let mut direction = ray.direction;
-        origin.x_r = self.cos_theta * ray.origin.x_r - self.sin_theta * ray.origin.z_b;
-        origin.z_b = self.sin_theta * ray.origin.x_r + self.cos_theta * ray.origin.z_b;
-        direction.x_r = self.cos_theta * ray.direction.x_r - self.sin_theta * ray.direction.z_b;
-        direction.z_b = self.sin_theta * ray.direction.x_r + self.cos_theta * ray.direction.z_b;
-        let rotated_ray = Ray::new(origin, direction, ray.time);
-        if let Some(mut rec) = self.hittable.hit(&rotated_ray, t_min, t_max) {
-            let mut p = rec.p;
-            let mut normal = rec.normal;
-            p.x_r = self.cos_theta * rec.p.x_r + self.sin_theta * rec.p.z_b;
-            p.z_b = -self.sin_theta * rec.p.x_r + self.cos_theta * rec.p.z_b;
-            normal.x_r = self.cos_theta * rec.normal.x_r + self.sin_theta * rec.normal.z_b;
-            normal.z_b = -self.sin_theta * rec.normal.x_r + self.cos_theta * rec.normal.z_b;
-            rec.p = p;
-            let ray = &rotated_ray;
-            rec.set_face_normal(ray, normal);
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut Random<f64>) -> Option<HitRecord> {
+        let rotated_ray = Ray::new(
+            self.to_local(ray.origin),
+            self.to_local(ray.direction),
+            ray.time,
+        );
+        if let Some(mut rec) = self.hittable.hit(&rotated_ray, t_min, t_max, rng) {
+            // The inner hit already chose the side and oriented the normal.
+            rec.p = self.to_world(rec.p);
+            rec.normal = self.to_world(rec.normal);
             Some(rec)
         } else {
             None
@@ -79,4 +74,65 @@ impl<H: Hittable + ?Sized> Hittable for YRotation<H> {
         *output_box = self.bbox;
         self.has_box
     }
+    fn pdf_value(&self, origin: Vec3, direction: Vec3, rng: &mut Random<f64>) -> f64 {
+        self.hittable
+            .pdf_value(self.to_local(origin), self.to_local(direction), rng)
+    }
+    fn random(&self, origin: Vec3, rng: &mut Random<f64>) -> Vec3 {
+        self.to_world(self.hittable.random(self.to_local(origin), rng))
+    }
+    /// Turns every light found inside by the same angle.
+    fn collect_lights(self: Arc<Self>, lights: &mut Vec<Arc<dyn Hittable>>) {
+        let mut inner = vec![];
+        self.hittable.clone().collect_lights(&mut inner);
+        lights.extend(inner.into_iter().map(|light| {
+            YRotation::with_sin_cos(light, self.sin_theta, self.cos_theta) as Arc<dyn Hittable>
+        }));
+    }
+}
+
+impl<H: Hittable + ?Sized> YRotation<H> {
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x_r - self.sin_theta * v.z_b,
+            v.y_g,
+            self.sin_theta * v.x_r + self.cos_theta * v.z_b,
+        )
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x_r + self.sin_theta * v.z_b,
+            v.y_g,
+            -self.sin_theta * v.x_r + self.cos_theta * v.z_b,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::{
+        material::lamberian::Lamberian, objects::rect::XyRect, random::SampleRng,
+        texture::solid_color::SolidColor,
+    };
+
+    #[test]
+    fn rotated_faces_keep_their_side() {
+        let material = Lamberian::new(SolidColor::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut rng = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        // The rect faces +z; turned by 180 degrees it faces -z.
+        let rect = YRotation::new(XyRect::new(-1., 1., -1., 1., 0., material), 180.);
+        for (z, front_face) in [(-5., true), (5., false)] {
+            let ray = Ray::new(Vec3::new(0.2, 0.3, z), Vec3::new(0., 0., -z), 0.);
+            let rec = rect
+                .hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut rng)
+                .unwrap();
+            assert_eq!(rec.front_face, front_face);
+            // The normal always points back towards the ray.
+            assert!(Vec3::length(rec.normal - Vec3::new(0., 0., z.signum())) < 1e-9);
+            assert!(Vec3::length(rec.p - Vec3::new(0.2, 0.3, 0.)) < 1e-9);
+        }
+    }
 }
@@ -0,0 +1,28 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis whose `w` axis points along a given direction.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: Vec3) -> Self {
+        let w = Vec3::unit_vector(n);
+        let a = if w.x_r.abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v = Vec3::unit_vector(Vec3::cross(w, a));
+        let u = Vec3::cross(w, v);
+        Self { u, v, w }
+    }
+
+    /// Turns coordinates in this basis into world coordinates.
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x_r + self.v * a.y_g + self.w * a.z_b
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use exr::prelude::f16;
+use image::{
+    codecs::{
+        bmp::BmpEncoder,
+        hdr::HdrEncoder,
+        jpeg::JpegEncoder,
+        pnm::{PnmEncoder, PnmSubtype, SampleEncoding},
+        tiff::TiffEncoder,
+        webp::WebPEncoder,
+    },
+    ColorType, ImageEncoder, Rgb,
+};
+
+use crate::{renderer::Framebuffer, tone_map::ToneMapper};
+
+/// Quality of JPEG files, from 1 to 100.
+const JPEG_QUALITY: u8 = 95;
+
+/// File format of a rendered image, chosen by the extension of its path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// JPEG (`.jpg` or `.jpeg`).
+    Jpeg,
+    /// TIFF (`.tif` or `.tiff`).
+    Tiff,
+    /// Lossless WebP (`.webp`).
+    WebP,
+    Bmp,
+    /// Binary portable pixmap (`.ppm`).
+    Ppm,
+    /// OpenEXR (`.exr`).
+    OpenExr,
+    /// Radiance RGBE (`.hdr`), which cannot store negative values.
+    RadianceHdr,
+    /// Portable float map (`.pfm`).
+    Pfm,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+            "tif" | "tiff" => Ok(ImageFormat::Tiff),
+            "webp" => Ok(ImageFormat::WebP),
+            "bmp" => Ok(ImageFormat::Bmp),
+            "ppm" => Ok(ImageFormat::Ppm),
+            "exr" => Ok(ImageFormat::OpenExr),
+            "hdr" => Ok(ImageFormat::RadianceHdr),
+            "pfm" => Ok(ImageFormat::Pfm),
+            _ => Err(format!(
+                "{}: unsupported image format, use .png, .jpg, .tif, .webp, .bmp, .ppm, \
+                 .exr, .hdr or .pfm",
+                path.display()
+            )
+            .into()),
+        }
+    }
+
+    /// Whether the format stores the linear radiance as it is, as floating-point
+    /// numbers, rather than tone mapped to 8 or 16 bits.
+    pub fn is_hdr(self) -> bool {
+        matches!(
+            self,
+            ImageFormat::OpenExr | ImageFormat::RadianceHdr | ImageFormat::Pfm
+        )
+    }
+
+    /// Whether the format can store 16 bits per channel.
+    pub fn supports_16_bit(self) -> bool {
+        matches!(self, ImageFormat::Png | ImageFormat::Tiff)
+    }
+}
+
+/// How an image was rendered, stored with it so that it can be reproduced: in UTF-8
+/// text (iTXt) chunks of PNG files, and in a JSON file next to files of other formats
+/// (`render1.jpg.json` for `render1.jpg`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderMetadata {
+    pub scene: String,
+    /// Samples taken per pixel, on average over the image.
+    pub samples_per_pixel: f64,
+    pub max_depth: u8,
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub render_time: Duration,
+}
+
+impl RenderMetadata {
+    /// Name and value of every entry, as written to PNG iTXt chunks.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "Software",
+                format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            ),
+            ("Scene", self.scene.clone()),
+            ("Samples per pixel", self.samples_per_pixel.to_string()),
+            ("Max depth", self.max_depth.to_string()),
+            ("Resolution", format!("{}x{}", self.width, self.height)),
+            ("Seed", self.seed.to_string()),
+            (
+                "Render time",
+                format!("{:.3} s", self.render_time.as_secs_f64()),
+            ),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        let string = |text: &str| {
+            let mut escaped = String::from("\"");
+            for c in text.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            escaped
+        };
+        let fields = [
+            ("software", string(env!("CARGO_PKG_NAME"))),
+            ("version", string(env!("CARGO_PKG_VERSION"))),
+            ("scene", string(&self.scene)),
+            ("samples_per_pixel", self.samples_per_pixel.to_string()),
+            ("max_depth", self.max_depth.to_string()),
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("seed", self.seed.to_string()),
+            (
+                "render_time_seconds",
+                format!("{:.3}", self.render_time.as_secs_f64()),
+            ),
+        ];
+        let body: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!("  \"{}\": {}", name, value))
+            .collect();
+        format!("{{\n{}\n}}\n", body.join(",\n"))
+    }
+}
+
+/// Writes framebuffers to image files in the format their extension asks for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImageOutput {
+    /// Used by 8- and 16-bit formats; high dynamic range ones store the radiance
+    /// unchanged.
+    pub tone_mapper: ToneMapper,
+    /// Whether OpenEXR files store 16-bit half floats instead of 32-bit floats.
+    pub half_float: bool,
+    /// Whether PNG and TIFF files store 16 bits per channel instead of 8.
+    pub sixteen_bit: bool,
+}
+
+impl ImageOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn tone_mapper(mut self, tone_mapper: ToneMapper) -> Self {
+        self.tone_mapper = tone_mapper;
+        self
+    }
+    pub fn half_float(mut self, half_float: bool) -> Self {
+        self.half_float = half_float;
+        self
+    }
+    pub fn sixteen_bit(mut self, sixteen_bit: bool) -> Self {
+        self.sixteen_bit = sixteen_bit;
+        self
+    }
+
+    /// Writes `framebuffer` to `path`, together with `metadata` if there is any.
+    pub fn save(
+        &self,
+        framebuffer: &Framebuffer,
+        path: &Path,
+        metadata: Option<&RenderMetadata>,
+    ) -> Result<(), Box<dyn Error>> {
+        let format = ImageFormat::from_path(path)?;
+        let (width, height) = (framebuffer.width, framebuffer.height);
+        let sixteen_bit = self.sixteen_bit && format.supports_16_bit();
+        let writer = || -> Result<BufWriter<File>, Box<dyn Error>> {
+            Ok(BufWriter::new(File::create(path)?))
+        };
+        match format {
+            ImageFormat::Png => {
+                // Encoded in memory first, so that a failing encoder leaves no truncated
+                // file behind.
+                let mut encoded = vec![];
+                let mut encoder = png::Encoder::new(&mut encoded, width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                // iTXt chunks hold UTF-8, so any scene path fits.
+                for (keyword, text) in metadata.map(RenderMetadata::entries).unwrap_or_default() {
+                    encoder.add_itxt_chunk(keyword.to_string(), text)?;
+                }
+                if sixteen_bit {
+                    encoder.set_depth(png::BitDepth::Sixteen);
+                    let image = self.tone_mapper.to_rgb16_image(framebuffer);
+                    let data: Vec<u8> =
+                        image.iter().flat_map(|value| value.to_be_bytes()).collect();
+                    encoder.write_header()?.write_image_data(&data)?;
+                } else {
+                    let image = self.tone_mapper.to_rgb_image(framebuffer);
+                    encoder.write_header()?.write_image_data(&image)?;
+                }
+                fs::write(path, encoded)?;
+            }
+            ImageFormat::Tiff if sixteen_bit => {
+                let image = self.tone_mapper.to_rgb16_image(framebuffer);
+                let data: Vec<u8> = image.iter().flat_map(|value| value.to_ne_bytes()).collect();
+                TiffEncoder::new(writer()?).write_image(&data, width, height, ColorType::Rgb16)?;
+            }
+            ImageFormat::Jpeg
+            | ImageFormat::Tiff
+            | ImageFormat::WebP
+            | ImageFormat::Bmp
+            | ImageFormat::Ppm => {
+                let image = self.tone_mapper.to_rgb_image(framebuffer);
+                let mut writer = writer()?;
+                match format {
+                    ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)
+                        .write_image(&image, width, height, ColorType::Rgb8)?,
+                    ImageFormat::Tiff => TiffEncoder::new(&mut writer).write_image(
+                        &image,
+                        width,
+                        height,
+                        ColorType::Rgb8,
+                    )?,
+                    ImageFormat::WebP => WebPEncoder::new_lossless(&mut writer).write_image(
+                        &image,
+                        width,
+                        height,
+                        ColorType::Rgb8,
+                    )?,
+                    ImageFormat::Bmp => BmpEncoder::new(&mut writer).write_image(
+                        &image,
+                        width,
+                        height,
+                        ColorType::Rgb8,
+                    )?,
+                    _ => PnmEncoder::new(&mut writer)
+                        .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                        .write_image(&image, width, height, ColorType::Rgb8)?,
+                }
+                writer.flush()?;
+            }
+            ImageFormat::OpenExr => self.save_exr(framebuffer, path)?,
+            ImageFormat::RadianceHdr => {
+                let pixels: Vec<Rgb<f32>> = framebuffer
+                    .pixels
+                    .iter()
+                    .map(|pixel| {
+                        Rgb([
+                            pixel.x_r.max(0.) as f32,
+                            pixel.y_g.max(0.) as f32,
+                            pixel.z_b.max(0.) as f32,
+                        ])
+                    })
+                    .collect();
+                HdrEncoder::new(writer()?).encode(&pixels, width as usize, height as usize)?;
+            }
+            ImageFormat::Pfm => {
+                let mut writer = writer()?;
+                // A negative scale marks little-endian data, stored bottom row first.
+                write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+                for y in (0..height).rev() {
+                    for x in 0..width {
+                        let pixel = framebuffer.get_pixel(x, y);
+                        for component in [pixel.x_r, pixel.y_g, pixel.z_b] {
+                            writer.write_all(&(component as f32).to_le_bytes())?;
+                        }
+                    }
+                }
+                writer.flush()?;
+            }
+        }
+        if let Some(metadata) = metadata.filter(|_| format != ImageFormat::Png) {
+            fs::write(format!("{}.json", path.display()), metadata.to_json())?;
+        }
+        Ok(())
+    }
+
+    fn save_exr(&self, framebuffer: &Framebuffer, path: &Path) -> Result<(), Box<dyn Error>> {
+        let (width, height) = (framebuffer.width as usize, framebuffer.height as usize);
+        let pixel = |x: usize, y: usize| {
+            let pixel = framebuffer.get_pixel(x as u32, y as u32);
+            (pixel.x_r as f32, pixel.y_g as f32, pixel.z_b as f32)
+        };
+        if self.half_float {
+            exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                let (r, g, b) = pixel(x, y);
+                (f16::from_f32(r), f16::from_f32(g), f16::from_f32(b))
+            })?;
+        } else {
+            exr::prelude::write_rgb_file(path, width, height, pixel)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use exr::{image::FlatSamples, meta::attribute::SampleType};
+    use image::codecs::hdr::HdrDecoder;
+
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn png_metadata_keeps_any_scene_path() {
+        let path =
+            std::env::temp_dir().join(format!("raytracing_output_test_{}.png", std::process::id()));
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.put_pixel(1, 1, Vec3::new(0.5, 0.25, 1.));
+        let metadata = RenderMetadata {
+            scene: "シーン/ün.scene".to_string(),
+            samples_per_pixel: 4.,
+            max_depth: 8,
+            width: 3,
+            height: 2,
+            seed: 42,
+            render_time: Duration::from_millis(1500),
+        };
+        ImageOutput::new()
+            .save(&framebuffer, &path, Some(&metadata))
+            .unwrap();
+
+        let decoder = png::Decoder::new(BufReader::new(File::open(&path).unwrap()));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        let mut text: Vec<(String, String)> = reader
+            .info()
+            .utf8_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.get_text().unwrap()))
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        let mut expected: Vec<(String, String)> = metadata
+            .entries()
+            .into_iter()
+            .map(|(keyword, text)| (keyword.to_string(), text))
+            .collect();
+        text.sort();
+        expected.sort();
+        assert_eq!(text, expected);
+        assert_eq!(&pixels[12..15], &[188, 137, 255]);
+    }
+
+    /// A 3×2 image with values beyond [0, 1], different in every pixel.
+    fn high_dynamic_range() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let value = (y * 3 + x) as f64;
+                framebuffer.put_pixel(x, y, Vec3::new(value * 0.5, 2.5, -0.25 * value));
+            }
+        }
+        framebuffer
+    }
+
+    fn save(output: ImageOutput, framebuffer: &Framebuffer, extension: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "raytracing_output_test_{}.{}",
+            std::process::id(),
+            extension
+        ));
+        output.save(framebuffer, &path, None).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn pfm_stores_little_endian_rows_bottom_up() {
+        let framebuffer = high_dynamic_range();
+        let bytes = save(ImageOutput::new(), &framebuffer, "pfm");
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(values.len(), 3 * 2 * 3);
+        for (index, pixel) in values.chunks_exact(3).enumerate() {
+            let (x, y) = (index as u32 % 3, 1 - index as u32 / 3);
+            let expected = framebuffer.get_pixel(x, y);
+            assert_eq!(
+                pixel,
+                [
+                    expected.x_r as f32,
+                    expected.y_g as f32,
+                    expected.z_b as f32
+                ]
+            );
+        }
+        assert_eq!(values[..3], [1.5, 2.5, -0.75]);
+    }
+
+    #[test]
+    fn exr_round_trips_half_and_full_floats() {
+        let framebuffer = high_dynamic_range();
+        for (half_float, sample_type) in [(true, SampleType::F16), (false, SampleType::F32)] {
+            let path = std::env::temp_dir().join(format!(
+                "raytracing_output_test_{}_{}.exr",
+                std::process::id(),
+                half_float
+            ));
+            ImageOutput::new()
+                .half_float(half_float)
+                .save(&framebuffer, &path, None)
+                .unwrap();
+            let image = exr::prelude::read_first_flat_layer_from_file(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(image.layer_data.size, exr::math::Vec2(3, 2));
+            let channels = &image.layer_data.channel_data.list;
+            let channel = |name: &str| {
+                channels
+                    .iter()
+                    .find(|channel| channel.name.to_string() == name)
+                    .unwrap()
+            };
+            for channel in channels {
+                let stored = match channel.sample_data {
+                    FlatSamples::F16(_) => SampleType::F16,
+                    FlatSamples::F32(_) => SampleType::F32,
+                    FlatSamples::U32(_) => SampleType::U32,
+                };
+                assert_eq!(stored, sample_type);
+            }
+            for y in 0..2 {
+                for x in 0..3 {
+                    let index = (y * 3 + x) as usize;
+                    let value = |name| {
+                        channel(name)
+                            .sample_data
+                            .value_by_flat_index(index)
+                            .to_f32()
+                    };
+                    let expected = framebuffer.get_pixel(x, y);
+                    assert_eq!(
+                        [value("R"), value("G"), value("B")],
+                        [
+                            expected.x_r as f32,
+                            expected.y_g as f32,
+                            expected.z_b as f32
+                        ]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_hdr_clamps_negative_values_to_zero() {
+        let framebuffer = high_dynamic_range();
+        let bytes = save(ImageOutput::new(), &framebuffer, "hdr");
+        // Read directly, as `image::load_from_memory` would make 8-bit pixels of it.
+        let pixels = HdrDecoder::new(&bytes[..])
+            .unwrap()
+            .read_image_hdr()
+            .unwrap();
+        for y in 0..2 {
+            for x in 0..3 {
+                let expected = framebuffer.get_pixel(x, y);
+                let pixel = pixels[(y * 3 + x) as usize];
+                // RGBE keeps 8 bits of mantissa, shared by the three channels.
+                assert!((pixel[0] as f64 - expected.x_r).abs() < 0.02);
+                assert!((pixel[1] as f64 - expected.y_g).abs() < 0.02);
+                assert_eq!(pixel[2], 0.);
+            }
+        }
+    }
+}
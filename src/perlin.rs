@@ -13,8 +13,7 @@ impl Perlin {
         let ranvec = (0..256)
             .map(|_| Vec3::random(random, Some(-1.0), Some(1.0)))
             .collect();
-        let mut rand = rand::thread_rng();
-        let mut rng = Random::new(&mut rand, Uniform::new(0usize, 256));
+        let mut rng = Random::new(random.rng.fork(), Uniform::new(0usize, 256));
         let perm_x = Perlin::perlin_generate_perm(&mut rng);
         let perm_y = Perlin::perlin_generate_perm(&mut rng);
         let perm_z = Perlin::perlin_generate_perm(&mut rng);
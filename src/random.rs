@@ -1,31 +1,93 @@
 use std::ops::{Add, Mul, Sub};
 
-use rand::{distributions::Uniform, rngs::ThreadRng, Rng};
+use rand::{distributions::Uniform, Rng, RngCore};
+
+/// SplitMix64 generator.
+///
+/// Seeding is just a hash, so every pixel sample gets its own independent stream
+/// derived from `(seed, pixel, sample)`. The image therefore only depends on the seed,
+/// not on how rayon distributes the work across threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleRng {
+    pub state: u64,
+}
+
+impl SampleRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Self::mix(seed),
+        }
+    }
+
+    pub fn for_pixel(seed: u64, pixel: u64) -> Self {
+        Self::new(Self::mix(seed) ^ pixel)
+    }
+
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Self {
+        Self::new(Self::mix(Self::mix(seed) ^ pixel) ^ sample)
+    }
+
+    /// Splits off an independent generator, advancing this one.
+    pub fn fork(&mut self) -> Self {
+        Self::new(self.next_u64())
+    }
+
+    #[inline(always)]
+    fn mix(value: u64) -> u64 {
+        let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RngCore for SampleRng {
+    #[inline(always)]
+    fn next_u64(&mut self) -> u64 {
+        let z = Self::mix(self.state);
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
 
 pub struct Random<
-    'a,
     T: rand::distributions::uniform::SampleUniform
         + Sub<Output = T>
         + Mul<Output = T>
         + Add<Output = T>
         + Copy,
 > {
-    pub rng: &'a mut ThreadRng,
+    pub rng: SampleRng,
     pub uniform: Uniform<T>,
 }
 
 impl<
-        'a,
         T: rand::distributions::uniform::SampleUniform
             + Sub<Output = T>
             + Mul<Output = T>
             + Add<Output = T>
             + Copy,
-    > Random<'a, T>
+    > Random<T>
 where
     T::Sampler: Copy,
 {
-    pub fn new(rng: &'a mut ThreadRng, uniform: Uniform<T>) -> Self {
+    pub fn new(rng: SampleRng, uniform: Uniform<T>) -> Self {
         Self { rng, uniform }
     }
     pub fn random(&mut self, min: Option<T>, max: Option<T>) -> T {
@@ -0,0 +1,880 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    ops::Range,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use rand::distributions::Uniform;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    aov::Aov,
+    background::Background,
+    camera::Camera,
+    film::{Film, FilmTile, PixelStats, TileBounds},
+    filter::Filter,
+    hittable::HittableList,
+    integrator::{Integrator, IntegratorKind},
+    material::Lobe,
+    random::{Random, SampleRng},
+    ray::Ray,
+    scene::Scene,
+    tone_map::ToneMapper,
+    vec3::Vec3,
+    Hittable,
+};
+
+/// Edge length in pixels of the square tiles the image is split into for rendering.
+const TILE_SIZE: u32 = 32;
+
+/// Most samples per pixel AOVs are averaged over; their first hits converge quickly.
+const AOV_SAMPLES: u32 = 16;
+
+/// Stops sampling pixels once their noise falls below a threshold and spends the
+/// samples saved on the pixels that are still noisy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Samples every pixel gets before its noise is estimated.
+    pub min_samples_per_pixel: u32,
+    pub max_samples_per_pixel: u32,
+    /// Noise, as measured by `PixelStats::error`, below which a pixel is done.
+    pub noise_threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(noise_threshold: f64) -> Self {
+        Self {
+            min_samples_per_pixel: 16,
+            max_samples_per_pixel: 4096,
+            noise_threshold,
+        }
+    }
+    pub fn min_samples_per_pixel(mut self, min_samples_per_pixel: u32) -> Self {
+        self.min_samples_per_pixel = min_samples_per_pixel;
+        self
+    }
+    pub fn max_samples_per_pixel(mut self, max_samples_per_pixel: u32) -> Self {
+        self.max_samples_per_pixel = max_samples_per_pixel;
+        self
+    }
+}
+
+/// How the light sampled directly and the light found by following the BSDF are
+/// weighted against each other (multiple importance sampling). Each of the two
+/// estimates of the same light counts most where its own sampling is the likelier one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MisHeuristic {
+    /// Weights proportional to the densities.
+    Balance,
+    /// Weights proportional to the squared densities, which leaves less noise where
+    /// one strategy is much better than the other.
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample drawn with density `pdf` by one strategy, when the other
+    /// one would have drawn it with density `other_pdf`.
+    pub fn weight(self, pdf: f64, other_pdf: f64) -> f64 {
+        let ratio = other_pdf / pdf;
+        match self {
+            MisHeuristic::Balance => 1. / (1. + ratio),
+            MisHeuristic::Power => 1. / (1. + ratio * ratio),
+        }
+    }
+}
+
+impl FromStr for MisHeuristic {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "balance" => Ok(MisHeuristic::Balance),
+            "power" => Ok(MisHeuristic::Power),
+            _ => Err(format!("unknown MIS heuristic '{}'", name).into()),
+        }
+    }
+}
+
+impl Display for MisHeuristic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MisHeuristic::Balance => "balance",
+            MisHeuristic::Power => "power",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Most bounces of each kind a path may follow, on top of the overall
+/// `RenderSettings::max_depth`; `None` leaves a kind limited only by that.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BounceLimits {
+    pub diffuse: Option<u8>,
+    pub glossy: Option<u8>,
+    pub transmission: Option<u8>,
+    pub volume: Option<u8>,
+}
+
+impl BounceLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn diffuse(mut self, diffuse: u8) -> Self {
+        self.diffuse = Some(diffuse);
+        self
+    }
+    pub fn glossy(mut self, glossy: u8) -> Self {
+        self.glossy = Some(glossy);
+        self
+    }
+    pub fn transmission(mut self, transmission: u8) -> Self {
+        self.transmission = Some(transmission);
+        self
+    }
+    pub fn volume(mut self, volume: u8) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+    pub fn limit(&self, lobe: Lobe) -> Option<u8> {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Glossy => self.glossy,
+            Lobe::Transmission => self.transmission,
+            Lobe::Volume => self.volume,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel, or the average over the image with adaptive sampling.
+    pub samples_per_pixel: u32,
+    /// Most rays traced per path, counting the camera ray, so paths follow at most
+    /// `max_depth - 1` bounces.
+    pub max_depth: u8,
+    pub bounces: BounceLimits,
+    /// Bounces every path follows before Russian roulette may end it early.
+    pub roulette_depth: u8,
+    /// Number of worker threads; `None` uses rayon's global thread pool.
+    pub threads: Option<usize>,
+    /// Seed of all random sampling; the same seed always gives the same image.
+    pub seed: u64,
+    /// Reconstruction filter the samples are splatted into the film with.
+    pub filter: Filter,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub mis: MisHeuristic,
+    pub integrator: IntegratorKind,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            samples_per_pixel: 1000,
+            max_depth: 50,
+            bounces: BounceLimits::default(),
+            roulette_depth: 3,
+            threads: None,
+            seed: 0,
+            filter: Filter::default(),
+            adaptive: None,
+            mis: MisHeuristic::default(),
+            integrator: IntegratorKind::default(),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+    pub fn max_depth(mut self, max_depth: u8) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    pub fn bounces(mut self, bounces: BounceLimits) -> Self {
+        self.bounces = bounces;
+        self
+    }
+    pub fn roulette_depth(mut self, roulette_depth: u8) -> Self {
+        self.roulette_depth = roulette_depth;
+        self
+    }
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+    pub fn adaptive(mut self, adaptive: AdaptiveSampling) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+    pub fn mis(mut self, mis: MisHeuristic) -> Self {
+        self.mis = mis;
+        self
+    }
+    pub fn integrator(mut self, integrator: IntegratorKind) -> Self {
+        self.integrator = integrator;
+        self
+    }
+    /// Samples per pixel of each pass when the image is not rendered progressively:
+    /// all of them at once, or with adaptive sampling the minimum first and then as
+    /// many again per pass for the pixels that are still noisy.
+    pub fn single_pass_samples(&self) -> u32 {
+        match self.adaptive {
+            Some(adaptive) => adaptive.min_samples_per_pixel,
+            None => self.samples_per_pixel,
+        }
+    }
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+}
+
+/// Linear radiance per pixel, stored row by row starting at the top-left corner.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Vec3::default(); width as usize * height as usize],
+        }
+    }
+    pub fn get_pixel(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+    pub fn put_pixel(&mut self, x: u32, y: u32, color: Vec3) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+    /// 8-bit sRGB image of the framebuffer, with radiance above 1 clamped; see
+    /// `ToneMapper` for other ways to fit it into range.
+    pub fn to_rgb_image(&self) -> image::RgbImage {
+        ToneMapper::default().to_rgb_image(self)
+    }
+}
+
+pub struct Renderer {
+    pub camera: Camera,
+    pub world: Arc<HittableList>,
+    /// Emissive objects of the world, and the environment map if the background is
+    /// one, that are sampled directly at every bounce.
+    pub lights: HittableList,
+    pub background: Background,
+    /// What each camera ray brings back, built from `settings.integrator`.
+    pub integrator: Arc<dyn Integrator>,
+    pub settings: RenderSettings,
+}
+
+impl Renderer {
+    /// Fails if `settings.integrator` can't be built for the scene.
+    pub fn new(scene: Scene, settings: RenderSettings) -> Result<Self, Box<dyn Error>> {
+        let integrator = settings
+            .integrator
+            .build(scene.world.as_ref(), &scene.camera)?;
+        let mut lights = scene.world.lights();
+        if let Background::Environment(map) = &scene.background {
+            lights.objects.push(map.clone());
+        }
+        Ok(Self {
+            camera: scene.camera,
+            lights,
+            world: scene.world,
+            background: scene.background,
+            integrator,
+            settings,
+        })
+    }
+
+    pub fn render(&self) -> Result<Framebuffer, Box<dyn Error>> {
+        self.render_with_progress(|_, _| {})
+    }
+
+    /// Renders the image, calling `on_progress(samples_done, samples_total)` after every
+    /// tile. Both counts are in pixel samples.
+    pub fn render_with_progress(
+        &self,
+        on_progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<Framebuffer, Box<dyn Error>> {
+        self.render_progressive(
+            self.settings.single_pass_samples(),
+            |_, _| Ok(()),
+            on_progress,
+        )
+    }
+
+    /// Renders the image in passes of `samples_per_pass` samples per pixel, each over
+    /// the whole image, and calls `on_pass(film, finished)` after every pass, e.g. to
+    /// write out the image so far. An error from `on_pass` stops the render.
+    ///
+    /// With adaptive sampling the first pass brings every pixel to the minimum sample
+    /// count, and later passes only sample the pixels that are still noisy, the
+    /// noisiest first.
+    ///
+    /// Every sample has its own random stream, so the final image does not depend on
+    /// how the samples were split into passes.
+    pub fn render_progressive(
+        &self,
+        samples_per_pass: u32,
+        on_pass: impl FnMut(&Film, bool) -> Result<(), Box<dyn Error>>,
+        on_progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<Framebuffer, Box<dyn Error>> {
+        let film = Film::new(self.settings.width, self.settings.height);
+        self.resume_progressive(film, samples_per_pass, on_pass, on_progress)
+    }
+
+    /// Continues a progressive render from the samples already in `film`, e.g. one
+    /// loaded from a checkpoint. The result is the same as that of an uninterrupted
+    /// render.
+    pub fn resume_progressive(
+        &self,
+        mut film: Film,
+        samples_per_pass: u32,
+        mut on_pass: impl FnMut(&Film, bool) -> Result<(), Box<dyn Error>>,
+        on_progress: impl FnMut(u64, u64) + Send,
+    ) -> Result<Framebuffer, Box<dyn Error>> {
+        if (film.width, film.height) != (self.settings.width, self.settings.height) {
+            return Err(format!(
+                "film is {}x{}, but the render is {}x{}",
+                film.width, film.height, self.settings.width, self.settings.height
+            )
+            .into());
+        }
+        let pool = self.thread_pool()?;
+        let mut progress = Progress {
+            done: film.sample_count(),
+            total: film.pixel_count() * self.settings.samples_per_pixel as u64,
+            on_progress,
+        };
+        let mut plan = self.plan_pass(&film, samples_per_pass);
+        while plan.iter().any(|samples| !samples.is_empty()) {
+            let mut pass = || self.render_pass(&mut film, &plan, &mut progress);
+            match &pool {
+                Some(pool) => pool.install(pass),
+                None => pass(),
+            }
+            plan = self.plan_pass(&film, samples_per_pass);
+            on_pass(&film, plan.iter().all(Range::is_empty))?;
+        }
+        // Adaptive sampling usually finishes before the whole budget is spent.
+        if progress.done < progress.total {
+            progress.done = progress.total;
+            (progress.on_progress)(progress.done, progress.total);
+        }
+        Ok(film.to_framebuffer())
+    }
+
+    /// Renders the given AOVs from the first hits of the camera rays of the first
+    /// samples of every pixel, one buffer per AOV in the same order. Each pixel is the
+    /// plain average of its samples, without the reconstruction filter.
+    pub fn render_aovs(&self, aovs: &[Aov]) -> Result<Vec<Framebuffer>, Box<dyn Error>> {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let rows = || -> Vec<Vec<(Vec<Vec3>, usize)>> {
+            (0..height)
+                .into_par_iter()
+                .map(|y| (0..width).map(|x| self.aov_pixel(x, y, aovs)).collect())
+                .collect()
+        };
+        let rows = match self.thread_pool()? {
+            Some(pool) => pool.install(rows),
+            None => rows(),
+        };
+        let mut buffers = vec![Framebuffer::new(width, height); aovs.len()];
+        let mut material_ids = HashMap::from([(0, 0)]);
+        for (index, (values, material_key)) in rows.into_iter().flatten().enumerate() {
+            for (aov, (buffer, value)) in aovs.iter().zip(buffers.iter_mut().zip(values)) {
+                buffer.pixels[index] = match aov {
+                    Aov::MaterialId => {
+                        let next = material_ids.len();
+                        let id = *material_ids.entry(material_key).or_insert(next) as f64;
+                        Vec3::new(id, id, id)
+                    }
+                    _ => value,
+                };
+            }
+        }
+        Ok(buffers)
+    }
+
+    /// Averages the AOVs of one pixel, and finds the material most of its samples hit.
+    fn aov_pixel(&self, x: u32, y: u32, aovs: &[Aov]) -> (Vec<Vec3>, usize) {
+        let samples = self.settings.samples_per_pixel.min(AOV_SAMPLES);
+        let mut values = vec![Vec3::default(); aovs.len()];
+        let mut material_counts: HashMap<usize, u32> = HashMap::new();
+        let mut material_key = 0;
+        for sample in 0..samples {
+            let (mut rng, _, ray) = self.camera_sample(x, y, sample);
+            let key = match self.world.hit(&ray, f64::MIN_POSITIVE, f64::MAX, &mut rng) {
+                Some(rec) => {
+                    for (value, aov) in values.iter_mut().zip(aovs) {
+                        *value += aov.value(&rec, &self.camera);
+                    }
+                    Aov::material_key(&rec)
+                }
+                None => 0,
+            };
+            let count = material_counts.entry(key).or_insert(0);
+            *count += 1;
+            let count = *count;
+            // Ties go to the material that got there first.
+            if sample == 0 || count > material_counts[&material_key] {
+                material_key = key;
+            }
+        }
+        for value in &mut values {
+            *value /= samples as f64;
+        }
+        (values, material_key)
+    }
+
+    /// Decides which samples every pixel gets in the next pass, as a range of sample
+    /// indices per pixel in film order; all of them are empty once the render is done.
+    fn plan_pass(&self, film: &Film, samples_per_pass: u32) -> Vec<Range<u32>> {
+        let samples_per_pass = samples_per_pass.max(1);
+        let samples_per_pixel = self.settings.samples_per_pixel;
+        let adaptive = match self.settings.adaptive {
+            Some(adaptive) => adaptive,
+            None => {
+                return film
+                    .stats
+                    .iter()
+                    .map(|stats| {
+                        let end = (stats.count + samples_per_pass).min(samples_per_pixel);
+                        stats.count..end.max(stats.count)
+                    })
+                    .collect()
+            }
+        };
+        let max_samples = adaptive.max_samples_per_pixel;
+        let mut plan: Vec<Range<u32>> = film
+            .stats
+            .iter()
+            .map(|stats| stats.count..stats.count)
+            .collect();
+        if film
+            .stats
+            .iter()
+            .any(|stats| stats.count < adaptive.min_samples_per_pixel)
+        {
+            let min_samples = adaptive.min_samples_per_pixel.min(max_samples);
+            for (samples, stats) in plan.iter_mut().zip(&film.stats) {
+                samples.end = stats.count.max(min_samples);
+            }
+            return plan;
+        }
+        let mut budget =
+            (film.pixel_count() * samples_per_pixel as u64).saturating_sub(film.sample_count());
+        let errors: Vec<f64> = film.stats.iter().map(PixelStats::error).collect();
+        let (width, height) = (film.width as usize, film.height as usize);
+        let mut noisy: Vec<(f64, usize)> = vec![];
+        for (index, stats) in film.stats.iter().enumerate() {
+            if stats.count >= max_samples {
+                continue;
+            }
+            // A pixel whose few samples all happened to agree, e.g. all missing a small
+            // light, is not done while its neighbours are still noisy.
+            let (x, y) = (index % width, index / width);
+            let mut error: f64 = 0.;
+            for j in y.saturating_sub(1)..(y + 2).min(height) {
+                for i in x.saturating_sub(1)..(x + 2).min(width) {
+                    error = error.max(errors[j * width + i]);
+                }
+            }
+            if error > adaptive.noise_threshold {
+                noisy.push((error, index));
+            }
+        }
+        // Noisiest first, so that a running out budget goes where it is needed most.
+        noisy.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        for (_, index) in noisy {
+            if budget == 0 {
+                break;
+            }
+            let count = film.stats[index].count;
+            let samples = (samples_per_pass.min(max_samples - count) as u64).min(budget);
+            plan[index].end += samples as u32;
+            budget -= samples;
+        }
+        plan
+    }
+
+    /// Adds the planned samples of every pixel to the film.
+    fn render_pass<F: FnMut(u64, u64) + Send>(
+        &self,
+        film: &mut Film,
+        plan: &[Range<u32>],
+        progress: &mut Progress<F>,
+    ) {
+        let tiles = film.tiles(TILE_SIZE);
+        let merger = Mutex::new(TileMerger {
+            film,
+            pending: HashMap::new(),
+            next: 0,
+            progress,
+        });
+        tiles
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(index, bounds)| {
+                let tile = self.render_tile(bounds, plan);
+                merger.lock().unwrap().push(index, tile);
+            });
+    }
+
+    fn render_tile(&self, bounds: TileBounds, plan: &[Range<u32>]) -> FilmTile {
+        let mut tile = FilmTile::new(
+            bounds,
+            self.settings.filter,
+            self.settings.width,
+            self.settings.height,
+        );
+        for y in bounds.y0..bounds.y1 {
+            for x in bounds.x0..bounds.x1 {
+                let samples = plan[(y * self.settings.width + x) as usize].clone();
+                self.work(&mut tile, x, y, samples);
+            }
+        }
+        tile
+    }
+
+    /// Traces the given samples of the pixel at raster position `(x, y)`, where `(0, 0)`
+    /// is the top-left corner, and splats them into the tile.
+    fn work(&self, tile: &mut FilmTile, x: u32, y: u32, samples: Range<u32>) {
+        for sample in samples {
+            let (mut rng, film_position, ray) = self.camera_sample(x, y, sample);
+            let color = self.integrator.ray_color(&ray, self, &mut rng);
+            tile.add_sample((x, y), film_position, color);
+        }
+    }
+
+    /// Random stream, film position and camera ray of one sample of the pixel at `(x, y)`.
+    fn camera_sample(&self, x: u32, y: u32, sample: u32) -> (Random<f64>, (f64, f64), Ray) {
+        let pixel = y as u64 * self.settings.width as u64 + x as u64;
+        let mut rng = Random::new(
+            SampleRng::for_sample(self.settings.seed, pixel, sample as u64),
+            Uniform::new(0.0, 1.0),
+        );
+        let film_x = x as f64 + rng.random(None, None);
+        let film_y = y as f64 + rng.random(None, None);
+        // The camera's `t` runs from the bottom of the image upwards.
+        let u = film_x / self.settings.width as f64;
+        let v = 1. - film_y / self.settings.height as f64;
+        let ray = Camera::get_ray(&mut rng, self.camera, u, v);
+        (rng, (film_x, film_y), ray)
+    }
+
+    /// Pool of `settings.threads` threads, or `None` for rayon's global pool.
+    fn thread_pool(&self) -> Result<Option<rayon::ThreadPool>, Box<dyn Error>> {
+        Ok(match self.settings.threads {
+            Some(threads) => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()?,
+            ),
+            None => None,
+        })
+    }
+}
+
+struct Progress<F> {
+    done: u64,
+    total: u64,
+    on_progress: F,
+}
+
+/// Merges finished tiles into the film in tile order, whichever thread finishes first.
+///
+/// Neighbouring tiles overlap by the filter radius, and a fixed order of the
+/// floating-point additions keeps the image independent of the thread count.
+struct TileMerger<'a, F> {
+    film: &'a mut Film,
+    pending: HashMap<usize, FilmTile>,
+    next: usize,
+    progress: &'a mut Progress<F>,
+}
+
+impl<'a, F: FnMut(u64, u64)> TileMerger<'a, F> {
+    fn push(&mut self, index: usize, tile: FilmTile) {
+        let progress = &mut self.progress;
+        progress.done += tile.sample_count();
+        (progress.on_progress)(progress.done, progress.total);
+        self.pending.insert(index, tile);
+        while let Some(tile) = self.pending.remove(&self.next) {
+            self.film.merge_tile(&tile);
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{image_env_builder::ImageEnvBuilder, scene_file::SceneFile};
+
+    /// Renders the small area-light scene and returns the finished film.
+    fn render(settings: RenderSettings, samples_per_pass: u32) -> Film {
+        let mut random = Random::new(SampleRng::new(settings.seed), Uniform::new(0., 1.));
+        let scene = ImageEnvBuilder::build(4, settings.aspect_ratio(), &mut random);
+        let mut result = None;
+        Renderer::new(scene, settings)
+            .unwrap()
+            .render_progressive(
+                samples_per_pass,
+                |film, finished| {
+                    if finished {
+                        result = Some(film.clone());
+                    }
+                    Ok(())
+                },
+                |_, _| {},
+            )
+            .unwrap();
+        result.unwrap()
+    }
+
+    fn assert_same_film(a: &Film, b: &Film) {
+        assert_eq!(a.pixels, b.pixels);
+        assert_eq!(a.weights, b.weights);
+        assert_eq!(a.stats, b.stats);
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings::new()
+            .width(48)
+            .height(40)
+            .samples_per_pixel(4)
+            .max_depth(8)
+            .seed(42)
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_image_on_any_number_of_threads() {
+        let settings = settings();
+        let one = render(settings.threads(1), settings.single_pass_samples());
+        let four = render(settings.threads(4), settings.single_pass_samples());
+        assert_same_film(&one, &four);
+
+        let other_seed = render(settings.threads(1).seed(7), settings.single_pass_samples());
+        assert_ne!(one.pixels, other_seed.pixels);
+    }
+
+    #[test]
+    fn progressive_passes_render_the_same_image() {
+        let settings = settings();
+        let single = render(settings.threads(1), settings.single_pass_samples());
+        let passes = render(settings.threads(3), 1);
+        assert_same_film(&single, &passes);
+    }
+
+    #[test]
+    fn adaptive_sampling_is_reproducible() {
+        let adaptive = AdaptiveSampling::new(0.05)
+            .min_samples_per_pixel(2)
+            .max_samples_per_pixel(8);
+        let settings = settings().adaptive(adaptive);
+        let one = render(settings.threads(1), 2);
+        let four = render(settings.threads(4), 2);
+        assert_same_film(&one, &four);
+    }
+
+    #[test]
+    fn rays_leaving_a_surface_do_not_hit_it_again() {
+        let floors = [
+            "xz_rect -100 100 -100 100 0 (lambertian (solid 0.5 0.5 0.5))",
+            "triangle -100 0 -100 100 0 -100 100 0 100 (lambertian (solid 0.5 0.5 0.5))
+             triangle -100 0 -100 100 0 100 -100 0 100 (lambertian (solid 0.5 0.5 0.5))",
+        ];
+        for floor in floors {
+            let source = format!(
+                "camera {{ lookfrom 0 6 8 lookat 0 0 0 vfov 30 aperture 0 }}
+                 background solid 1 1 1
+                 {}",
+                floor
+            );
+            let settings = settings().max_depth(2);
+            let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+            let scene = SceneFile::parse(
+                &source,
+                Path::new("."),
+                settings.aspect_ratio(),
+                &mut random,
+            )
+            .unwrap();
+            let image = Renderer::new(scene, settings).unwrap().render().unwrap();
+            for pixel in image.pixels {
+                assert!(Vec3::length(pixel - Vec3::new(0.5, 0.5, 0.5)) < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn mis_weights_of_both_strategies_sum_to_one() {
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            for (a, b) in [(1., 1.), (0.3, 2.5), (10., 0.01), (1e-6, 4.)] {
+                let sum = heuristic.weight(a, b) + heuristic.weight(b, a);
+                assert!((sum - 1.).abs() < 1e-12, "{}: {} {}", heuristic, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn mis_finds_the_same_light_as_bsdf_sampling_alone() {
+        let source = "camera { lookfrom 0 4 3 lookat 0 0 0 vfov 40 aperture 0 }
+             background solid 0 0 0
+             xz_rect -4 4 -4 4 0 (lambertian (solid 0.5 0.5 0.5))
+             xz_rect -1 1 -1 1 1.5 (diffuse_light (solid 4 4 4))";
+        let mean = |settings: RenderSettings, sample_lights: bool| {
+            let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+            let scene =
+                SceneFile::parse(source, Path::new("."), settings.aspect_ratio(), &mut random)
+                    .unwrap();
+            let mut renderer = Renderer::new(scene, settings).unwrap();
+            assert_eq!(renderer.lights.objects.len(), 1);
+            if !sample_lights {
+                renderer.lights.objects.clear();
+            }
+            let image = renderer.render().unwrap();
+            image.pixels.iter().map(|&pixel| pixel.y_g).sum::<f64>() / image.pixels.len() as f64
+        };
+        // Direct light only, so the light is found in a single bounce.
+        let settings = settings().max_depth(2).samples_per_pixel(16);
+        let bsdf_only = mean(settings.samples_per_pixel(64), false);
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let mis = mean(settings.mis(heuristic), true);
+            assert!(
+                (mis - bsdf_only).abs() < 0.03 * bsdf_only,
+                "{}: {} != {}",
+                heuristic,
+                mis,
+                bsdf_only
+            );
+        }
+    }
+
+    /// Renders `aovs` of a scene seen from the origin down -z, 90° across vertically.
+    fn render_aovs(objects: &str, aovs: &[Aov]) -> Vec<Framebuffer> {
+        let source = format!(
+            "camera {{ lookfrom 0 0 0 lookat 0 0 -1 vfov 90 aperture 0 }}
+             {}",
+            objects
+        );
+        let settings = settings();
+        let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        let scene = SceneFile::parse(
+            &source,
+            Path::new("."),
+            settings.aspect_ratio(),
+            &mut random,
+        )
+        .unwrap();
+        Renderer::new(scene, settings)
+            .unwrap()
+            .render_aovs(aovs)
+            .unwrap()
+    }
+
+    #[test]
+    fn aovs_describe_the_first_hit() {
+        // A wall facing the camera that fills the left half of the image.
+        let buffers = render_aovs(
+            "xy_rect -10 0 -10 10 -2 (lambertian (solid 0.2 0.4 0.6))",
+            &Aov::ALL,
+        );
+        let [albedo, normal, depth, position, id] = &buffers[..] else {
+            panic!("{} buffers", buffers.len());
+        };
+        for y in 0..40 {
+            for x in 0..20 {
+                assert!(Vec3::length(albedo.get_pixel(x, y) - Vec3::new(0.2, 0.4, 0.6)) < 1e-12);
+                assert!(Vec3::length(normal.get_pixel(x, y) - Vec3::new(0., 0., 1.)) < 1e-12);
+                // Along the view direction, not the distance to the camera.
+                assert!(Vec3::length(depth.get_pixel(x, y) - Vec3::new(2., 2., 2.)) < 1e-12);
+                let p = position.get_pixel(x, y);
+                assert!((p.z_b + 2.).abs() < 1e-12 && p.x_r < 0.);
+                // Where the camera ray through the pixel centre meets the wall, to
+                // within a pixel.
+                let pixel_size = 4. / 40.;
+                let expected_x = (x as f64 + 0.5) * pixel_size - 2.4;
+                let expected_y = 2. - (y as f64 + 0.5) * pixel_size;
+                assert!((p.x_r - expected_x).abs() < pixel_size);
+                assert!((p.y_g - expected_y).abs() < pixel_size);
+                assert_eq!(id.get_pixel(x, y), Vec3::new(1., 1., 1.));
+            }
+            for x in 28..48 {
+                for buffer in &buffers {
+                    assert_eq!(buffer.get_pixel(x, y), Vec3::default());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn material_ids_are_shared_by_objects_of_the_same_material() {
+        let buffers = render_aovs(
+            "material paint lambertian (solid 0.5 0.5 0.5)
+             xy_rect -10 -0.5 -10 10 -2 paint
+             xy_rect 0.5 10 0 10 -2 paint
+             xy_rect 0.5 10 -10 -0.5 -2 (lambertian (solid 0.5 0.5 0.5))",
+            &[Aov::MaterialId],
+        );
+        let id = |x, y| buffers[0].get_pixel(x, y).x_r;
+        assert_eq!(id(5, 5), 1.);
+        assert_eq!(id(40, 5), 1.);
+        assert_eq!(id(40, 35), 2.);
+        // The gaps between the walls.
+        assert_eq!(id(24, 5), 0.);
+        assert_eq!(id(40, 20), 0.);
+    }
+
+    #[test]
+    fn integrators_sized_by_the_scene_need_a_bounding_box() {
+        let source = "camera { lookfrom 0 0 5 lookat 0 0 0 vfov 40 aperture 0 }";
+        for (integrator, ok) in [
+            ("path", true),
+            ("ao", false),
+            ("ao:2", true),
+            ("depth", false),
+        ] {
+            let settings = settings().integrator(integrator.parse().unwrap());
+            let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+            let scene =
+                SceneFile::parse(source, Path::new("."), settings.aspect_ratio(), &mut random)
+                    .unwrap();
+            assert_eq!(Renderer::new(scene, settings).is_ok(), ok, "{}", integrator);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use crate::{
+    background::Background, camera::Camera, hittable::HittableList, integrator::IntegratorKind,
+};
+
+/// Everything a render needs besides its settings.
+#[derive(Clone)]
+pub struct Scene {
+    pub camera: Camera,
+    pub world: Arc<HittableList>,
+    pub background: Background,
+    /// Integrator the scene asks to be rendered with, unless the render settings
+    /// choose another one.
+    pub integrator: Option<IntegratorKind>,
+}
+
+impl Scene {
+    /// A scene with a black background, lit only by its own lights.
+    pub fn new(camera: Camera, world: Arc<HittableList>) -> Self {
+        Self {
+            camera,
+            world,
+            background: Background::default(),
+            integrator: None,
+        }
+    }
+    pub fn background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+    pub fn integrator(mut self, integrator: IntegratorKind) -> Self {
+        self.integrator = Some(integrator);
+        self
+    }
+}
@@ -0,0 +1,113 @@
+//! Loader for the text scene description format (`*.scene`).
+//!
+//! The format is documented in the "Scene files" section of `README.md`.
+
+use std::{error::Error, fmt::Display, fs, path::Path};
+
+use crate::{random::Random, scene::Scene};
+
+mod lexer;
+mod parser;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl SceneError {
+    pub fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for SceneError {}
+
+pub struct SceneFile {}
+
+impl SceneFile {
+    pub fn load(
+        path: &Path,
+        aspect_ratio: f64,
+        random: &mut Random<f64>,
+    ) -> Result<Scene, Box<dyn Error>> {
+        let source = fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::parse(&source, base_dir, aspect_ratio, random)
+            .map_err(|err| format!("{}:{}", path.display(), err).into())
+    }
+
+    /// Parses scene source text. Relative image paths are resolved against `base_dir`,
+    /// `aspect_ratio` is used unless the camera block sets its own.
+    pub fn parse(
+        source: &str,
+        base_dir: &Path,
+        aspect_ratio: f64,
+        random: &mut Random<f64>,
+    ) -> Result<Scene, SceneError> {
+        let tokens = lexer::Lexer::new(source).tokenize()?;
+        parser::Parser::new(tokens, base_dir, aspect_ratio, random).parse_scene()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::random::SampleRng;
+
+    fn parse_error(source: &str) -> SceneError {
+        let mut random = Random::new(SampleRng::new(0), Uniform::new(0., 1.));
+        match SceneFile::parse(source, Path::new("."), 1., &mut random) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn lexer_errors_point_at_the_character() {
+        let err = parse_error("sphere 0 0 0 1 @");
+        assert_eq!((err.line, err.column), (1, 16));
+        assert_eq!(err.message, "unexpected character '@'");
+    }
+
+    #[test]
+    fn parser_errors_point_at_the_token() {
+        let err = parse_error("# comment\n\n    cylinder 0 0 0 1 m\n");
+        assert_eq!((err.line, err.column), (3, 5));
+        assert_eq!(err.message, "expected an object, found 'cylinder'");
+
+        let err = parse_error("camera {\n  lookfrom 0 0 0\n  zoom 2\n}");
+        assert_eq!((err.line, err.column), (3, 3));
+        assert_eq!(err.message, "unknown camera setting 'zoom'");
+    }
+
+    #[test]
+    fn empty_blocks_are_rejected() {
+        let err = parse_error("sphere 0 0 0 1 (metal 1 1 1 0)\n  group { }");
+        assert_eq!((err.line, err.column), (2, 3));
+        assert_eq!(err.message, "group block must not be empty");
+
+        let err = parse_error("bvh {\n}");
+        assert_eq!((err.line, err.column), (1, 1));
+        assert_eq!(err.message, "bvh block must not be empty");
+    }
+
+    #[test]
+    fn camera_time_must_precede_bvh_blocks() {
+        let err = parse_error("bvh { sphere 0 0 0 1 (metal 1 1 1 0) }\ncamera { time 0 2 }");
+        assert_eq!((err.line, err.column), (2, 10));
+    }
+}
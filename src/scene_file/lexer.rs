@@ -0,0 +1,159 @@
+use crate::scene_file::SceneError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TokenKind {
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::Ident(name) => format!("'{}'", name),
+            TokenKind::Number(value) => format!("number {}", value),
+            TokenKind::Str(value) => format!("string \"{}\"", value),
+            TokenKind::LBrace => "'{'".to_string(),
+            TokenKind::RBrace => "'}'".to_string(),
+            TokenKind::LParen => "'('".to_string(),
+            TokenKind::RParen => "')'".to_string(),
+            TokenKind::Eof => "end of file".to_string(),
+        }
+    }
+}
+
+pub struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<Token>, SceneError> {
+        let mut tokens = vec![];
+        loop {
+            let token = self.next_token()?;
+            let is_eof = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if is_eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c == '#' {
+                while let Some(&c) = self.chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.bump();
+                }
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, SceneError> {
+        self.skip_whitespace_and_comments();
+        let (line, column) = (self.line, self.column);
+        let token = |kind| Token { kind, line, column };
+        let c = match self.chars.peek() {
+            Some(&c) => c,
+            None => return Ok(token(TokenKind::Eof)),
+        };
+        match c {
+            '{' | '}' | '(' | ')' => {
+                self.bump();
+                Ok(token(match c {
+                    '{' => TokenKind::LBrace,
+                    '}' => TokenKind::RBrace,
+                    '(' => TokenKind::LParen,
+                    _ => TokenKind::RParen,
+                }))
+            }
+            '"' => {
+                self.bump();
+                let mut value = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(SceneError::new(line, column, "unterminated string"))
+                        }
+                        Some(c) => value.push(c),
+                    }
+                }
+                Ok(token(TokenKind::Str(value)))
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut text = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == '+' || c == '.' {
+                        text.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                text.parse::<f64>()
+                    .map(|value| token(TokenKind::Number(value)))
+                    .map_err(|_| {
+                        SceneError::new(line, column, format!("invalid number '{}'", text))
+                    })
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        name.push(c);
+                        self.bump();
+                    } else {
+                        break;
+                    }
+                }
+                Ok(token(TokenKind::Ident(name)))
+            }
+            _ => Err(SceneError::new(
+                line,
+                column,
+                format!("unexpected character '{}'", c),
+            )),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    background::{Background, EnvironmentMap},
+    bvh_node::BvhNode,
+    camera::Camera,
+    hittable::HittableList,
+    integrator::IntegratorKind,
+    loaders::{gltf::GltfLoader, obj::ObjLoader, ply::PlyLoader},
+    material::{
+        dielectric::Dielectric, diffuse_light::DiffuseLight, isotropic::Isotropic,
+        lamberian::Lamberian, metal::Metal, Material,
+    },
+    objects::{
+        box_render::BoxRender,
+        constant_medium::ConstantMedium,
+        moving_sphere::MovingSphere,
+        rect::{XyRect, XzRect, YzRect},
+        sphere::Sphere,
+        translate::Translate,
+        triangle::Triangle,
+        y_rotation::YRotation,
+    },
+    random::Random,
+    scene::Scene,
+    scene_file::{
+        lexer::{Token, TokenKind},
+        SceneError,
+    },
+    texture::{
+        checker_texture::CheckerTexture, image_texture::ImageTexture, noise_texture::NoiseTexture,
+        solid_color::SolidColor, vertex_color::VertexColor, Texture,
+    },
+    vec3::Vec3,
+    Hittable,
+};
+
+pub struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    base_dir: PathBuf,
+    aspect_ratio: f64,
+    random: &'a mut Random<f64>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Camera shutter interval that `bvh` blocks build their boxes for.
+    shutter: (f64, f64),
+    /// Whether a `bvh` block has been built with `shutter` already.
+    bvh_built: bool,
+}
+
+struct CameraSettings {
+    lookfrom: Vec3,
+    lookat: Vec3,
+    vup: Vec3,
+    vfov: f64,
+    aspect: f64,
+    aperture: f64,
+    time0: f64,
+    time1: f64,
+}
+
+impl CameraSettings {
+    fn new(aspect: f64) -> Self {
+        Self {
+            lookfrom: Vec3::new(13., 2., 3.),
+            lookat: Vec3::new(0., 0., 0.),
+            vup: Vec3::new(0., 1., 0.),
+            vfov: 20.,
+            aspect,
+            aperture: 0.1,
+            time0: 0.,
+            time1: 1.,
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(
+        tokens: Vec<Token>,
+        base_dir: &Path,
+        aspect_ratio: f64,
+        random: &'a mut Random<f64>,
+    ) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            base_dir: base_dir.to_path_buf(),
+            aspect_ratio,
+            random,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            shutter: (0., 1.),
+            bvh_built: false,
+        }
+    }
+
+    pub fn parse_scene(mut self) -> Result<Scene, SceneError> {
+        let mut camera = CameraSettings::new(self.aspect_ratio);
+        let mut background = Background::default();
+        let mut integrator = None;
+        let mut world = HittableList::new(vec![]);
+        while self.peek().kind != TokenKind::Eof {
+            let token = self.peek().clone();
+            match &token.kind {
+                TokenKind::Ident(keyword) if keyword == "camera" => {
+                    self.advance();
+                    self.parse_camera(&mut camera)?;
+                }
+                TokenKind::Ident(keyword) if keyword == "background" => {
+                    self.advance();
+                    background = self.parse_background()?;
+                }
+                TokenKind::Ident(keyword) if keyword == "integrator" => {
+                    self.advance();
+                    let (name, name_token) = self.expect_ident()?;
+                    let parameter = match self.peek().kind {
+                        TokenKind::Number(_) => Some(self.expect_number()?),
+                        _ => None,
+                    };
+                    integrator = Some(
+                        IntegratorKind::new(&name, parameter)
+                            .map_err(|err| Self::error_at(&name_token, err.to_string()))?,
+                    );
+                }
+                TokenKind::Ident(keyword) if keyword == "texture" => {
+                    self.advance();
+                    let (name, name_token) = self.expect_ident()?;
+                    let texture = self.parse_texture_expr()?;
+                    if self.textures.insert(name.clone(), texture).is_some() {
+                        return Err(Self::error_at(
+                            &name_token,
+                            format!("texture '{}' is already defined", name),
+                        ));
+                    }
+                }
+                TokenKind::Ident(keyword) if keyword == "material" => {
+                    self.advance();
+                    let (name, name_token) = self.expect_ident()?;
+                    let material = self.parse_material_expr()?;
+                    if self.materials.insert(name.clone(), material).is_some() {
+                        return Err(Self::error_at(
+                            &name_token,
+                            format!("material '{}' is already defined", name),
+                        ));
+                    }
+                }
+                _ => {
+                    let object = self.parse_object()?;
+                    world.objects.push(object);
+                }
+            }
+        }
+
+        let mut return_world = HittableList::new(vec![]);
+        if !world.objects.is_empty() {
+            return_world.objects.push(Arc::new(BvhNode::new(
+                &mut world.objects,
+                camera.time0,
+                camera.time1,
+            )));
+        }
+        let camera = Camera::new(
+            camera.lookfrom,
+            camera.lookat,
+            camera.vup,
+            camera.vfov,
+            camera.aspect,
+            camera.aperture,
+            camera.time0,
+            camera.time1,
+        );
+        let mut scene = Scene::new(camera, Arc::from(return_world)).background(background);
+        scene.integrator = integrator;
+        Ok(scene)
+    }
+
+    fn parse_camera(&mut self, camera: &mut CameraSettings) -> Result<(), SceneError> {
+        self.expect(TokenKind::LBrace)?;
+        loop {
+            if self.peek().kind == TokenKind::RBrace {
+                self.advance();
+                return Ok(());
+            }
+            let (field, field_token) = self.expect_ident()?;
+            match field.as_str() {
+                "lookfrom" => camera.lookfrom = self.expect_vec3()?,
+                "lookat" => camera.lookat = self.expect_vec3()?,
+                "vup" => camera.vup = self.expect_vec3()?,
+                "vfov" => camera.vfov = self.expect_number()?,
+                "aspect" => camera.aspect = self.expect_number()?,
+                "aperture" => camera.aperture = self.expect_number()?,
+                "time" => {
+                    if self.bvh_built {
+                        return Err(Self::error_at(
+                            &field_token,
+                            "the camera time must be set before the first bvh block",
+                        ));
+                    }
+                    camera.time0 = self.expect_number()?;
+                    camera.time1 = self.expect_number()?;
+                    self.shutter = (camera.time0, camera.time1);
+                }
+                _ => {
+                    return Err(Self::error_at(
+                        &field_token,
+                        format!("unknown camera setting '{}'", field),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse_background(&mut self) -> Result<Background, SceneError> {
+        let (kind, token) = self.expect_ident()?;
+        match kind.as_str() {
+            "solid" => Ok(Background::Solid(self.expect_vec3()?)),
+            "gradient" => {
+                let bottom = self.expect_vec3()?;
+                let top = self.expect_vec3()?;
+                Ok(Background::Gradient { bottom, top })
+            }
+            "sky" => Ok(Background::sky()),
+            "environment" => {
+                let (path, path_token) = self.expect_string()?;
+                let rotation = self.expect_number()?;
+                let intensity = self.expect_number()?;
+                let full_path = self.base_dir.join(&path);
+                let map = EnvironmentMap::new(&full_path)
+                    .map_err(|err| Self::error_at(&path_token, err.to_string()))?;
+                Ok(Background::Environment(Arc::new(
+                    map.rotation(rotation).intensity(intensity),
+                )))
+            }
+            _ => Err(Self::error_at(
+                &token,
+                format!("unknown background type '{}'", kind),
+            )),
+        }
+    }
+
+    /// A texture reference: either the name of a defined texture or a
+    /// parenthesized texture expression.
+    fn parse_texture(&mut self) -> Result<Arc<dyn Texture>, SceneError> {
+        if self.peek().kind == TokenKind::LParen {
+            self.advance();
+            let texture = self.parse_texture_expr()?;
+            self.expect(TokenKind::RParen)?;
+            return Ok(texture);
+        }
+        let (name, token) = self.expect_ident()?;
+        self.textures
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| Self::error_at(&token, format!("undefined texture '{}'", name)))
+    }
+
+    fn parse_texture_expr(&mut self) -> Result<Arc<dyn Texture>, SceneError> {
+        let (kind, token) = self.expect_ident()?;
+        match kind.as_str() {
+            "solid" => Ok(SolidColor::new(self.expect_vec3()?)),
+            "checker" => {
+                let odd = self.parse_texture()?;
+                let even = self.parse_texture()?;
+                Ok(CheckerTexture::new(odd, even))
+            }
+            "noise" => {
+                let scale = self.expect_number()?;
+                Ok(Arc::new(NoiseTexture::new(self.random, scale)))
+            }
+            "image" => {
+                let (path, path_token) = self.expect_string()?;
+                let full_path = self.base_dir.join(&path);
+                ImageTexture::new(&full_path)
+                    .map(|texture| texture as Arc<dyn Texture>)
+                    .map_err(|err| {
+                        Self::error_at(
+                            &path_token,
+                            format!("cannot load image '{}': {}", full_path.display(), err),
+                        )
+                    })
+            }
+            "vertex_color" => Ok(VertexColor::new(self.parse_texture()?)),
+            _ => Err(Self::error_at(
+                &token,
+                format!("unknown texture type '{}'", kind),
+            )),
+        }
+    }
+
+    /// A material reference: either the name of a defined material or a
+    /// parenthesized material expression.
+    fn parse_material(&mut self) -> Result<Arc<dyn Material>, SceneError> {
+        if self.peek().kind == TokenKind::LParen {
+            self.advance();
+            let material = self.parse_material_expr()?;
+            self.expect(TokenKind::RParen)?;
+            return Ok(material);
+        }
+        let (name, token) = self.expect_ident()?;
+        self.materials
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| Self::error_at(&token, format!("undefined material '{}'", name)))
+    }
+
+    fn parse_material_expr(&mut self) -> Result<Arc<dyn Material>, SceneError> {
+        let (kind, token) = self.expect_ident()?;
+        match kind.as_str() {
+            "lambertian" => Ok(Lamberian::new(self.parse_texture()?)),
+            "metal" => {
+                let albedo = self.expect_vec3()?;
+                let fuzz = self.expect_number()?;
+                Ok(Metal::new(albedo, fuzz))
+            }
+            "dielectric" => Ok(Dielectric::new(self.expect_number()?)),
+            "diffuse_light" => Ok(DiffuseLight::new(self.parse_texture()?)),
+            "isotropic" => Ok(Isotropic::new(self.parse_texture()?)),
+            _ => Err(Self::error_at(
+                &token,
+                format!("unknown material type '{}'", kind),
+            )),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Arc<dyn Hittable>, SceneError> {
+        let (kind, token) = self.expect_ident()?;
+        match kind.as_str() {
+            "sphere" => {
+                let center = self.expect_vec3()?;
+                let radius = self.expect_number()?;
+                Ok(Sphere::new(center, radius, self.parse_material()?))
+            }
+            "moving_sphere" => {
+                let center0 = self.expect_vec3()?;
+                let center1 = self.expect_vec3()?;
+                let time0 = self.expect_number()?;
+                let time1 = self.expect_number()?;
+                let radius = self.expect_number()?;
+                Ok(Arc::new(MovingSphere::new(
+                    center0,
+                    center1,
+                    time0,
+                    time1,
+                    radius,
+                    self.parse_material()?,
+                )))
+            }
+            "xy_rect" | "xz_rect" | "yz_rect" => {
+                let a0 = self.expect_number()?;
+                let a1 = self.expect_number()?;
+                let b0 = self.expect_number()?;
+                let b1 = self.expect_number()?;
+                let k = self.expect_number()?;
+                let material = self.parse_material()?;
+                Ok(match kind.as_str() {
+                    "xy_rect" => XyRect::new(a0, a1, b0, b1, k, material),
+                    "xz_rect" => XzRect::new(a0, a1, b0, b1, k, material),
+                    _ => YzRect::new(a0, a1, b0, b1, k, material),
+                })
+            }
+            "triangle" => {
+                let p0 = self.expect_vec3()?;
+                let p1 = self.expect_vec3()?;
+                let p2 = self.expect_vec3()?;
+                Ok(Triangle::new(p0, p1, p2, self.parse_material()?))
+            }
+            "box" => {
+                let p0 = self.expect_vec3()?;
+                let p1 = self.expect_vec3()?;
+                Ok(BoxRender::new(p0, p1, self.parse_material()?))
+            }
+            "obj" => {
+                let (path, path_token) = self.expect_string()?;
+                let default_material = self.parse_material()?;
+                let full_path = self.base_dir.join(&path);
+                ObjLoader::load(&full_path, default_material)
+                    .map(|mesh| mesh as Arc<dyn Hittable>)
+                    .map_err(|err| Self::error_at(&path_token, err.to_string()))
+            }
+            "ply" => {
+                let (path, path_token) = self.expect_string()?;
+                let material = self.parse_material()?;
+                let full_path = self.base_dir.join(&path);
+                PlyLoader::load(&full_path, material)
+                    .map(|mesh| mesh as Arc<dyn Hittable>)
+                    .map_err(|err| Self::error_at(&path_token, err.to_string()))
+            }
+            "gltf" => {
+                let (path, path_token) = self.expect_string()?;
+                let default_material = self.parse_material()?;
+                let full_path = self.base_dir.join(&path);
+                GltfLoader::load(&full_path, default_material, self.aspect_ratio)
+                    .map(|scene| scene.world as Arc<dyn Hittable>)
+                    .map_err(|err| Self::error_at(&path_token, err.to_string()))
+            }
+            "translate" => {
+                let offset = self.expect_vec3()?;
+                Ok(Translate::new(self.parse_object()?, offset))
+            }
+            "rotate_y" => {
+                let angle = self.expect_number()?;
+                Ok(YRotation::new(self.parse_object()?, angle))
+            }
+            "constant_medium" => {
+                let density = self.expect_number()?;
+                let phase_function = self.parse_material()?;
+                Ok(ConstantMedium::new(
+                    self.parse_object()?,
+                    density,
+                    phase_function,
+                ))
+            }
+            "group" => {
+                let objects = self.parse_object_block()?;
+                if objects.is_empty() {
+                    return Err(Self::error_at(&token, "group block must not be empty"));
+                }
+                Ok(Arc::new(HittableList::new(objects)))
+            }
+            "bvh" => {
+                let mut objects = self.parse_object_block()?;
+                if objects.is_empty() {
+                    return Err(Self::error_at(&token, "bvh block must not be empty"));
+                }
+                self.bvh_built = true;
+                let (time0, time1) = self.shutter;
+                Ok(Arc::new(BvhNode::new(&mut objects, time0, time1)))
+            }
+            _ => Err(Self::error_at(
+                &token,
+                format!("expected an object, found '{}'", kind),
+            )),
+        }
+    }
+
+    fn parse_object_block(&mut self) -> Result<Vec<Arc<dyn Hittable>>, SceneError> {
+        self.expect(TokenKind::LBrace)?;
+        let mut objects = vec![];
+        while self.peek().kind != TokenKind::RBrace {
+            objects.push(self.parse_object()?);
+        }
+        self.advance();
+        Ok(objects)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<Token, SceneError> {
+        let token = self.advance();
+        if token.kind == kind {
+            Ok(token)
+        } else {
+            Err(Self::error_at(
+                &token,
+                format!(
+                    "expected {}, found {}",
+                    kind.describe(),
+                    token.kind.describe()
+                ),
+            ))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<(String, Token), SceneError> {
+        let token = self.advance();
+        match &token.kind {
+            TokenKind::Ident(name) => Ok((name.clone(), token)),
+            other => Err(Self::error_at(
+                &token,
+                format!("expected a name, found {}", other.describe()),
+            )),
+        }
+    }
+
+    fn expect_number(&mut self) -> Result<f64, SceneError> {
+        let token = self.advance();
+        match token.kind {
+            TokenKind::Number(value) => Ok(value),
+            ref other => Err(Self::error_at(
+                &token,
+                format!("expected a number, found {}", other.describe()),
+            )),
+        }
+    }
+
+    fn expect_vec3(&mut self) -> Result<Vec3, SceneError> {
+        Ok(Vec3::new(
+            self.expect_number()?,
+            self.expect_number()?,
+            self.expect_number()?,
+        ))
+    }
+
+    fn expect_string(&mut self) -> Result<(String, Token), SceneError> {
+        let token = self.advance();
+        match &token.kind {
+            TokenKind::Str(value) => Ok((value.clone(), token)),
+            other => Err(Self::error_at(
+                &token,
+                format!("expected a quoted string, found {}", other.describe()),
+            )),
+        }
+    }
+
+    fn error_at(token: &Token, message: impl Into<String>) -> SceneError {
+        SceneError::new(token.line, token.column, message)
+    }
+}
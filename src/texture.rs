@@ -1,7 +1,12 @@
-use crate::vec3::Vec3;
+use crate::{hittable::HitRecord, vec3::Vec3};
 
 pub trait Texture: Send + Sync {
     fn color(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+
+    /// Color at a surface hit. Textures that need more than `(u, v, p)` override this.
+    fn value(&self, rec: &HitRecord) -> Vec3 {
+        self.color(rec.u, rec.v, rec.p)
+    }
 }
 
 pub mod checker_texture;
@@ -10,3 +15,5 @@ pub mod solid_color;
 pub mod noise_texture;
 
 pub mod image_texture;
+
+pub mod vertex_color;
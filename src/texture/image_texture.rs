@@ -2,9 +2,15 @@ use crate::vec3::Vec3;
 
 use crate::texture::Texture;
 
-use image::{self, io::Reader, GenericImageView};
+use image::{self, io::Reader, DynamicImage, GenericImageView};
 
-use std::{error::Error, fs::File, io::BufReader, path::Path, sync::Arc};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+    sync::Arc,
+};
 
 use image::Rgb32FImage;
 
@@ -20,14 +26,42 @@ impl ImageTexture {
         let buf_read = BufReader::new(r);
         let img_reader = Reader::new(buf_read);
         let img = img_reader.with_guessed_format()?.decode()?;
+        Ok(Self::from_image(img))
+    }
+
+    /// Decodes an encoded image (PNG, JPEG, ...) held in memory.
+    pub fn from_memory(bytes: &[u8]) -> Result<Arc<Self>, Box<dyn Error>> {
+        let img_reader = Reader::new(Cursor::new(bytes));
+        let img = img_reader.with_guessed_format()?.decode()?;
+        Ok(Self::from_image(img))
+    }
+
+    /// Texture of a color image; see `linear_rgb` for how its texels are read.
+    pub fn from_image(img: DynamicImage) -> Arc<Self> {
         let (width, height) = img.dimensions();
-        let data = img.into_rgb32f();
-        Ok(Arc::from(Self {
+        let data = linear_rgb(img);
+        Arc::from(Self {
             data: Some(data),
             width: width as usize,
             height: height as usize,
-        }))
+        })
+    }
+}
+
+/// Linear colors of `img`. Images with integer channels hold sRGB-encoded colors, which
+/// are decoded; floating-point ones are linear already.
+pub fn linear_rgb(img: DynamicImage) -> Rgb32FImage {
+    let linear = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let mut data = img.into_rgb32f();
+    if !linear {
+        for channel in data.iter_mut() {
+            *channel = Vec3::srgb_eotf(f64::from(*channel)) as f32;
+        }
     }
+    data
 }
 
 impl Texture for ImageTexture {
@@ -52,3 +86,33 @@ impl Texture for ImageTexture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn eight_bit_texels_are_decoded_from_srgb() {
+        let mut image = RgbImage::new(256, 1);
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            *pixel = Rgb([x as u8; 3]);
+        }
+        let texture = ImageTexture::from_image(DynamicImage::ImageRgb8(image));
+        for x in 0..256 {
+            let color = texture.color((x as f64 + 0.5) / 256., 0.5, Vec3::default());
+            assert_eq!(Vec3::to_rgb(color), Rgb([x as u8; 3]));
+        }
+        let middle = texture.color(128.5 / 256., 0.5, Vec3::default());
+        assert!((middle.x_r - 0.2158).abs() < 1e-4);
+    }
+
+    #[test]
+    fn floating_point_texels_are_linear() {
+        let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.25, 0.5, 2.]));
+        let texture = ImageTexture::from_image(DynamicImage::ImageRgb32F(image));
+        let color = texture.color(0.5, 0.5, Vec3::default());
+        assert_eq!(color, Vec3::new(0.25, 0.5, 2.));
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use crate::{hittable::HitRecord, texture::Texture, vec3::Vec3};
+
+/// Uses the interpolated vertex color of the mesh that was hit, and `fallback`
+/// on surfaces without vertex colors.
+pub struct VertexColor {
+    pub(crate) fallback: Arc<dyn Texture>,
+}
+
+impl VertexColor {
+    pub fn new(fallback: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::from(Self { fallback })
+    }
+}
+
+impl Texture for VertexColor {
+    fn color(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.fallback.color(u, v, p)
+    }
+
+    fn value(&self, rec: &HitRecord) -> Vec3 {
+        match rec.color {
+            Some(color) => color,
+            None => self.fallback.value(rec),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use crate::{renderer::Framebuffer, vec3::Vec3};
+
+/// Curve that compresses the unbounded radiance of a render into the range a display
+/// can show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Cuts off everything brighter than white.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` of the luminance, which never quite reaches white.
+    Reinhard,
+    /// Reinhard's curve stretched so that the white point maps to white.
+    ExtendedReinhard,
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2, with a toe and a long shoulder.
+    Filmic,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMapOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            "filmic" => Ok(ToneMapOperator::Filmic),
+            _ => Err(format!("unknown tone mapping operator '{}'", name).into()),
+        }
+    }
+}
+
+impl Display for ToneMapOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ToneMapOperator::Clamp => "clamp",
+            ToneMapOperator::Reinhard => "reinhard",
+            ToneMapOperator::ExtendedReinhard => "extended-reinhard",
+            ToneMapOperator::Aces => "aces",
+            ToneMapOperator::Filmic => "filmic",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Turns linear radiance into 8- or 16-bit sRGB: scales it by the exposure, applies the
+/// operator and encodes the result with the sRGB transfer function.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMapOperator,
+    /// Exposure adjustment in stops; every stop doubles the brightness.
+    pub exposure: f64,
+    /// Luminance, after exposure, that `ExtendedReinhard` maps to white; the brightest
+    /// pixel of the image if `None`.
+    pub white_point: Option<f64>,
+}
+
+impl ToneMapper {
+    pub fn new(operator: ToneMapOperator) -> Self {
+        Self {
+            operator,
+            ..Self::default()
+        }
+    }
+    pub fn exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        self
+    }
+    pub fn white_point(mut self, white_point: f64) -> Self {
+        self.white_point = Some(white_point);
+        self
+    }
+
+    pub fn to_rgb_image(&self, framebuffer: &Framebuffer) -> RgbImage {
+        let (scale, white_point) = self.scale_and_white_point(framebuffer);
+        RgbImage::from_fn(framebuffer.width, framebuffer.height, |x, y| {
+            let color = self.map(framebuffer.get_pixel(x, y) * scale, white_point);
+            Vec3::to_rgb(color)
+        })
+    }
+
+    /// Like `to_rgb_image`, with 16 bits per channel.
+    pub fn to_rgb16_image(&self, framebuffer: &Framebuffer) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+        let (scale, white_point) = self.scale_and_white_point(framebuffer);
+        ImageBuffer::from_fn(framebuffer.width, framebuffer.height, |x, y| {
+            let color = self.map(framebuffer.get_pixel(x, y) * scale, white_point);
+            let encode =
+                |value: f64| (Vec3::srgb_oetf(value.clamp(0., 1.)) * 65535.).round() as u16;
+            Rgb([encode(color.x_r), encode(color.y_g), encode(color.z_b)])
+        })
+    }
+
+    /// Factor the exposure scales radiance by, and the white point after exposure.
+    fn scale_and_white_point(&self, framebuffer: &Framebuffer) -> (f64, f64) {
+        let scale = self.exposure.exp2();
+        let white_point = self.white_point.unwrap_or_else(|| {
+            framebuffer
+                .pixels
+                .iter()
+                .map(|&pixel| Vec3::luminance(pixel) * scale)
+                .fold(0., f64::max)
+        });
+        (scale, white_point)
+    }
+
+    /// Display color, before sRGB encoding, of radiance `color` that is already exposed.
+    fn map(&self, color: Vec3, white_point: f64) -> Vec3 {
+        match self.operator {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard => color / (1. + Vec3::luminance(color)),
+            ToneMapOperator::ExtendedReinhard => {
+                let luminance = Vec3::luminance(color);
+                let white = white_point.max(f64::MIN_POSITIVE);
+                color * (1. + luminance / (white * white)) / (1. + luminance)
+            }
+            ToneMapOperator::Aces => {
+                // sRGB to the ACES working space, the fitted curve and back.
+                let input = [
+                    [0.59719, 0.35458, 0.04823],
+                    [0.07600, 0.90834, 0.01566],
+                    [0.02840, 0.13383, 0.83777],
+                ];
+                let output = [
+                    [1.60475, -0.53108, -0.07367],
+                    [-0.10208, 1.10813, -0.00605],
+                    [-0.00327, -0.07276, 1.07602],
+                ];
+                let fit = |v: f64| {
+                    (v * (v + 0.0245786) - 0.000090537)
+                        / (v * (0.983729 * v + 0.4329510) + 0.238081)
+                };
+                let v = Self::multiply(input, color);
+                let v = Self::multiply(output, Vec3::new(fit(v.x_r), fit(v.y_g), fit(v.z_b)));
+                // The fit dips below 0 near black and tends to 1.017, so it is clamped
+                // like the reference does.
+                Vec3::new(
+                    v.x_r.clamp(0., 1.),
+                    v.y_g.clamp(0., 1.),
+                    v.z_b.clamp(0., 1.),
+                )
+            }
+            ToneMapOperator::Filmic => {
+                let curve = |x: f64| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                // Hable's exposure bias of 2 and linear white point of 11.2.
+                let white_scale = 1. / curve(11.2);
+                let map = |v: f64| curve(2. * v) * white_scale;
+                Vec3::new(map(color.x_r), map(color.y_g), map(color.z_b))
+            }
+        }
+    }
+
+    fn multiply(matrix: [[f64; 3]; 3], v: Vec3) -> Vec3 {
+        let row = |row: [f64; 3]| row[0] * v.x_r + row[1] * v.y_g + row[2] * v.z_b;
+        Vec3::new(row(matrix[0]), row(matrix[1]), row(matrix[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f64) -> Vec3 {
+        Vec3::new(value, value, value)
+    }
+
+    #[test]
+    fn every_stop_of_exposure_doubles_the_radiance() {
+        let mut framebuffer = Framebuffer::new(1, 1);
+        framebuffer.put_pixel(0, 0, gray(0.125));
+        let (scale, white_point) = ToneMapper::new(ToneMapOperator::Clamp)
+            .exposure(1.)
+            .scale_and_white_point(&framebuffer);
+        assert_eq!((scale, white_point), (2., 0.25));
+        let (scale, _) = ToneMapper::default()
+            .exposure(-2.)
+            .scale_and_white_point(&framebuffer);
+        assert_eq!(scale, 0.25);
+
+        let exposed = ToneMapper::default()
+            .exposure(1.)
+            .to_rgb_image(&framebuffer);
+        framebuffer.put_pixel(0, 0, gray(0.25));
+        assert_eq!(exposed, ToneMapper::default().to_rgb_image(&framebuffer));
+    }
+
+    #[test]
+    fn operators_map_their_reference_points() {
+        let map =
+            |operator, value, white_point| ToneMapper::new(operator).map(gray(value), white_point);
+        let assert_gray = |color: Vec3, value: f64| {
+            for channel in [color.x_r, color.y_g, color.z_b] {
+                assert!((channel - value).abs() < 1e-12, "{color:?} != {value}");
+            }
+        };
+        assert_gray(map(ToneMapOperator::Clamp, 3., 0.), 3.);
+        assert_gray(map(ToneMapOperator::Reinhard, 1., 0.), 0.5);
+        assert_gray(map(ToneMapOperator::ExtendedReinhard, 4., 4.), 1.);
+        assert_gray(map(ToneMapOperator::ExtendedReinhard, 2., 4.), 0.75);
+        assert_gray(map(ToneMapOperator::Filmic, 11.2 / 2., 0.), 1.);
+        assert_gray(map(ToneMapOperator::Filmic, 0., 0.), 0.);
+    }
+
+    #[test]
+    fn aces_rises_steadily_and_stays_displayable() {
+        let mapper = ToneMapper::new(ToneMapOperator::Aces);
+        let mut previous = Vec3::new(-1., -1., -1.);
+        for step in 0..=1000 {
+            let value = 0.001 * step as f64 * step as f64 / 10.;
+            for color in [gray(value), Vec3::new(value, 0.5 * value, 0.1 * value)] {
+                let mapped = mapper.map(color, 0.);
+                for channel in [mapped.x_r, mapped.y_g, mapped.z_b] {
+                    assert!(
+                        (0. ..=1.).contains(&channel),
+                        "{color:?} maps to {mapped:?}"
+                    );
+                }
+            }
+            let mapped = mapper.map(gray(value), 0.);
+            assert!(mapped.x_r >= previous.x_r && mapped.y_g >= previous.y_g);
+            assert!(mapped.z_b >= previous.z_b);
+            previous = mapped;
+        }
+        assert!(previous.y_g > 0.99);
+    }
+
+    #[test]
+    fn to_rgb_encodes_black_and_white() {
+        assert_eq!(Vec3::to_rgb(gray(0.)), Rgb([0, 0, 0]));
+        assert_eq!(Vec3::to_rgb(gray(1.)), Rgb([255, 255, 255]));
+        assert_eq!(Vec3::to_rgb(Vec3::new(-1., 2., 0.5)), Rgb([0, 255, 188]));
+    }
+}
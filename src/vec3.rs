@@ -3,7 +3,7 @@ use std::{
     ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub},
 };
 
-use image::{Pixel, Rgb};
+use image::Rgb;
 
 use crate::random::Random;
 
@@ -38,6 +38,10 @@ impl Vec3 {
     pub fn dot(v: Self, rhs: Self) -> f64 {
         v.x_r * rhs.x_r + v.y_g * rhs.y_g + v.z_b * rhs.z_b
     }
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(v: Self) -> f64 {
+        0.2126 * v.x_r + 0.7152 * v.y_g + 0.0722 * v.z_b
+    }
     #[inline(always)]
     pub fn unit_vector(v: Vec3) -> Self {
         v / Vec3::length(v)
@@ -54,19 +58,13 @@ impl Vec3 {
         }
     }
 
+    /// Uniformly distributed point inside the unit sphere, which BSDF pdfs rely on.
     pub fn random_in_unit_sphere(rng: &mut Random<f64>) -> Self {
-        let min = -1.0;
-        let max = 1.0;
-        let rng_x = rng.random(Some(min), Some(max));
-        let rng_y = rng.random(Some(min + rng_x.abs()), Some(max - rng_x.abs()));
-        let rng_z = rng.random(
-            Some(min + rng_x.abs() + rng_y.abs()),
-            Some(max - rng_x.abs() - rng_y.abs()),
-        );
-        Self {
-            x_r: rng_x,
-            y_g: rng_y,
-            z_b: rng_z,
+        loop {
+            let p = Vec3::random(rng, Some(-1.), Some(1.));
+            if Vec3::length_squared(p) < 1. {
+                return p;
+            }
         }
     }
 
@@ -111,6 +109,33 @@ impl Vec3 {
         let r_out_parallel = n * -(1.0 - Vec3::length_squared(r_out_perp)).abs().sqrt();
         r_out_perp + r_out_parallel
     }
+
+    /// Converts a linear color to an 8-bit sRGB color, clamping what is out of range.
+    pub fn to_rgb(vec: Self) -> Rgb<u8> {
+        let encode = |value: f64| {
+            let value = value.clamp(0.0, 1.0);
+            (Vec3::srgb_oetf(value) * 255.).round() as u8
+        };
+        Rgb([encode(vec.x_r), encode(vec.y_g), encode(vec.z_b)])
+    }
+
+    /// The sRGB transfer function, from linear light in `[0, 1]` to the encoded value.
+    pub fn srgb_oetf(value: f64) -> f64 {
+        if value <= 0.0031308 {
+            12.92 * value
+        } else {
+            1.055 * value.powf(1. / 2.4) - 0.055
+        }
+    }
+
+    /// The inverse of `srgb_oetf`, from an encoded value in `[0, 1]` to linear light.
+    pub fn srgb_eotf(value: f64) -> f64 {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    }
 }
 
 impl From<Rgb<u8>> for Vec3 {
@@ -123,26 +148,6 @@ impl From<Rgb<u8>> for Vec3 {
     }
 }
 
-impl From<Vec3> for Rgb<u8> {
-    fn from(vec: Vec3) -> Self {
-        let mut r = vec.x_r;
-        let mut g = vec.y_g;
-        let mut b = vec.z_b;
-
-        let scale = 1.0 / crate::SAMPLES_PER_PIXEL as f64;
-        r = (scale * r).sqrt();
-        g = (scale * g).sqrt();
-        b = (scale * b).sqrt();
-
-        let ir: u8 = (r.clamp(0.0, 1.0) * 256.) as u8;
-        let ig: u8 = (g.clamp(0.0, 1.0) * 256.) as u8;
-        let ib: u8 = (b.clamp(0.0, 1.0) * 256.) as u8;
-        let arr: [u8; 3] = [ir, ig, ib];
-        let rgb = Rgb::from_slice(&arr);
-        *rgb
-    }
-}
-
 impl Add for Vec3 {
     type Output = Vec3;
 